@@ -0,0 +1,525 @@
+//! Synchronous TUS client, for consumers which don't run an async runtime
+//!
+//! Mirrors the core operations of `crate::client::Client`, sharing the operations, the
+//! response handling and the chunk `Session`, so only the requests are made here
+use crate::{
+    client::{
+        parse_host, redirect_policy, ChunkStep, ChunksizeOverride, ClientOptions, FullResponse,
+        Recovery, ServerInfoCache, Session,
+    },
+    error::TusError,
+    source::{ChunkSource, ReaderSource},
+    trace::{self, OpSpan},
+    tus::{
+        headers::{default_headers, TusHeaders, UPLOAD_CHECKSUM},
+        http::TusHttpMethod,
+        ops::TusOp,
+        token::UploadToken,
+        upload_meta::UploadMeta,
+        TusExtension, TusServerInfo, UploadStats, UploadStatus, SUPPORTED_VERSIONS,
+    },
+};
+use bytes::Bytes;
+use reqwest::{
+    blocking::{Client as RequestClient, Request, Response},
+    header::HeaderMap,
+    IntoUrl,
+};
+use std::{
+    collections::HashMap,
+    io::{Read, Seek},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+use url::Url;
+
+pub struct Client {
+    client: RequestClient,
+    options: ClientOptions,
+    server_info: ServerInfoCache,
+    chunksize: ChunksizeOverride,
+}
+
+impl Client {
+    /// Create a new blocking TUS Client
+    ///
+    /// # Panics
+    ///
+    /// Panics if the underlying http client cannot be built, use `Client::try_new` to handle
+    /// the error instead
+    pub fn new(options: ClientOptions) -> Self {
+        Self::try_new(options).expect("Failed to build TUS client")
+    }
+
+    /// Create a new blocking TUS Client, returning an error if the underlying http client
+    /// cannot be built from the `options`
+    pub fn try_new(options: ClientOptions) -> Result<Self, TusError> {
+        let mut builder = RequestClient::builder()
+            .gzip(options.accept_compression)
+            .brotli(options.accept_compression)
+            .redirect(redirect_policy());
+        if let Some(proxy) = options.load_proxy()? {
+            builder = builder.proxy(proxy);
+        }
+        if let Some(identity) = options.load_identity()? {
+            builder = builder.identity(identity);
+        }
+        if let Some(certificate) = options.load_root_certificate()? {
+            builder = builder.add_root_certificate(certificate);
+        }
+        if let Some(max_idle) = options.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+        if let Some(timeout) = options.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        let client = builder.build().map_err(TusError::ReqwestError)?;
+        Ok(Self {
+            client,
+            options,
+            server_info: ServerInfoCache::default(),
+            chunksize: ChunksizeOverride::default(),
+        })
+    }
+
+    /// Change the chunksize of the chunks sent after the current one, e.g. from another thread
+    /// while `resume` runs
+    ///
+    /// Returns `TusError::InvalidChunkSize` for 0, and `TusError::FileTooLarge` above the
+    /// `Tus-Max-Size` of a server whose info is cached with `server_info_ttl`
+    pub fn set_chunksize(&self, chunksize: usize) -> Result<(), TusError> {
+        self.chunksize
+            .set(&self.options, &self.server_info, chunksize)
+    }
+
+    /// Run TUS Operations
+    ///
+    /// Returns: `UploadMeta`
+    fn run(
+        &self,
+        op: TusOp,
+        metadata: &UploadMeta,
+        body: Option<Bytes>,
+    ) -> Result<UploadMeta, TusError> {
+        let metadata = &*self.options.creation_meta(op, metadata)?;
+        #[cfg(feature = "tracing")]
+        {
+            let span = trace::op_span(&op, metadata, body.as_deref());
+            let started = Instant::now();
+            let result = span.in_scope(|| self.send_retrying(op, metadata, body, &span));
+            trace::finish(&span, started, &result);
+            result
+        }
+        #[cfg(not(feature = "tracing"))]
+        self.send_retrying(op, metadata, body, &OpSpan::none())
+    }
+
+    /// Send the request, retrying with backoff while the upload is locked
+    fn send_retrying(
+        &self,
+        op: TusOp,
+        metadata: &UploadMeta,
+        body: Option<Bytes>,
+        span: &OpSpan,
+    ) -> Result<UploadMeta, TusError> {
+        self.retry_locked(span, || {
+            self.options.chunk_sending(op, metadata, body.as_deref());
+            // Cheap to clone for each attempt
+            let result = self.send(op, metadata, body.clone(), span);
+            self.options.chunk_acked(op, &result);
+            result
+        })
+    }
+
+    /// Make the request of `attempt`, again with backoff while the upload is locked
+    fn retry_locked<T>(
+        &self,
+        span: &OpSpan,
+        mut attempt: impl FnMut() -> Result<T, TusError>,
+    ) -> Result<T, TusError> {
+        let mut retries = 0;
+        loop {
+            let result = attempt();
+            let delay = match &result {
+                Err(TusError::Locked(_)) => self.options.locked_retry_delay(retries + 1),
+                _ => None,
+            };
+            match (result, delay) {
+                (Err(error), Some(delay)) => {
+                    retries += 1;
+                    trace::record_retry(span, retries, &error);
+                    std::thread::sleep(delay);
+                }
+                (result, _) => return result,
+            }
+        }
+    }
+
+    fn send(
+        &self,
+        op: TusOp,
+        metadata: &UploadMeta,
+        body: Option<Bytes>,
+        span: &OpSpan,
+    ) -> Result<UploadMeta, TusError> {
+        let headers = op.headers(metadata, body.as_deref(), &self.options)?;
+        let url = op.url_for_meta(metadata);
+        let request = self.make_request(&url, op.method(), headers, body)?;
+        let response = self.execute(request)?;
+        trace::record_status(span, response.status);
+        self.options.op_result(op, metadata, response)
+    }
+
+    /// Execute `request`, reading the whole response
+    fn execute(&self, request: Request) -> Result<FullResponse, TusError> {
+        let response = self
+            .client
+            .execute(request)
+            .map_err(TusError::from_reqwest)?;
+        Ok(read_response(response))
+    }
+
+    fn make_request(
+        &self,
+        url: &Url,
+        method: TusHttpMethod,
+        headers: HashMap<String, String>,
+        body: Option<Bytes>,
+    ) -> Result<Request, TusError> {
+        let (method, map) = self.options.request_parts(method, headers)?;
+        let mut request = self.client.request(method, url.clone()).headers(map);
+        if let Some(body) = body {
+            request = request.body(body);
+        }
+        request
+            .build()
+            .map_err(|e| TusError::RequestError(format!("{e}")))
+    }
+
+    /// Get the server info
+    ///
+    /// Cached per url for `ClientOptions::server_info_ttl` when set
+    pub fn get_server_info(&self, url: &Url) -> Result<TusServerInfo, TusError> {
+        if let Some(info) = self.server_info.get(url, self.options.server_info_ttl) {
+            return Ok(info);
+        }
+        let headers = HashMap::<String, String>::new();
+        let request = self.make_request(url, TusHttpMethod::Options, headers, None)?;
+        let info = self.options.server_info_result(self.execute(request)?)?;
+        if self.options.server_info_ttl.is_some() {
+            self.server_info.insert(url, &info);
+        }
+        Ok(info)
+    }
+
+    /// Forget the cached server info of `url`, so the next `get_server_info` fetches it
+    pub fn invalidate_server_info(&self, url: &Url) {
+        self.server_info.remove(url);
+    }
+
+    /// Check the server at `url` accepts an upload of `file` before creating it
+    pub fn check_file(&self, file: &Path, url: &Url) -> Result<TusServerInfo, TusError> {
+        let file_size = file.metadata()?.len() as usize;
+        let info = self.get_server_info(url)?;
+        info.check_size(file_size)?;
+        Ok(info)
+    }
+
+    /// Get the offset and length of any upload by its url, without an `UploadMeta`
+    pub fn inspect(&self, url: &Url) -> Result<UploadStatus, TusError> {
+        let headers = default_headers(&self.options.tus_version);
+        UploadStatus::from_headers(&self.head(url, headers)?)
+    }
+
+    /// Send a HEAD request for any upload by its url, returning all the parsed TUS headers,
+    /// e.g. its metadata or concatenation, for tooling and diagnostics
+    pub fn head_raw(&self, url: &Url) -> Result<TusHeaders, TusError> {
+        let headers = default_headers(&self.options.tus_version);
+        Ok(TusHeaders::from_headers(&self.head(url, headers)?))
+    }
+
+    /// Fetch the offset and length of an upload from the server, returning `meta` updated to
+    /// match, ready to `resume`
+    ///
+    /// Returns `TusError::NotFoundError` if the upload is gone
+    pub fn sync(&self, meta: &UploadMeta) -> Result<UploadMeta, TusError> {
+        meta.require_url()?;
+        match self.get_offset(meta) {
+            Err(TusError::Gone(body)) => Err(TusError::NotFoundError(body)),
+            result => result,
+        }
+    }
+
+    /// Check the file of a completed upload against the checksum the server computed
+    ///
+    /// Returns `TusError::VerificationUnsupported` if the server doesn't return an
+    /// `Upload-Checksum` for the upload
+    pub fn verify_upload(&self, meta: &UploadMeta) -> Result<bool, TusError> {
+        meta.require_url()?;
+        let op = TusOp::GetOffset;
+        let headers = op.headers(meta, None, &self.options)?;
+        let headers = self.head(&op.url_for_meta(meta), headers)?;
+        let checksum = headers
+            .get(UPLOAD_CHECKSUM)
+            .ok_or(TusError::VerificationUnsupported)?
+            .to_str()?;
+        meta.matches_checksum(checksum)
+    }
+
+    /// The headers of a successful HEAD request to `url` with `headers`, retried while the
+    /// upload is locked
+    fn head(&self, url: &Url, headers: HashMap<String, String>) -> Result<HeaderMap, TusError> {
+        self.retry_locked(&OpSpan::none(), || {
+            let request = self.make_request(url, TusHttpMethod::Head, headers.clone(), None)?;
+            self.options.head_result(self.execute(request)?)
+        })
+    }
+
+    /// Get the server info from the response to an OPTIONS request made elsewhere
+    pub fn server_info_from_response(&self, response: Response) -> Result<TusServerInfo, TusError> {
+        self.options.server_info_result(read_response(response))
+    }
+
+    /// Negotiate the TUS protocol version with the server
+    pub fn negotiate_version(&self, meta: &UploadMeta) -> Result<UploadMeta, TusError> {
+        let info = self.get_server_info(&meta.upload_host)?;
+        let mut preferred = vec![self.options.tus_version.as_str()];
+        preferred.extend(SUPPORTED_VERSIONS);
+        let version = info.negotiate_version(&preferred)?;
+        Ok(meta.with_version(version))
+    }
+
+    /// Create a resource on the server to upload a file
+    #[allow(clippy::ptr_arg)]
+    pub fn create(
+        &self,
+        file: &PathBuf,
+        host: &Url,
+        metadata: Option<HashMap<String, String>>,
+        custom_headers: Option<HashMap<String, String>>,
+    ) -> Result<UploadMeta, TusError> {
+        let mut meta = UploadMeta::new(file.clone(), host.clone(), None, metadata, custom_headers)?
+            .with_version(self.options.tus_version.clone());
+        if self.options.idempotent_create {
+            meta = meta.with_idempotency_key(meta.derive_idempotency_key()?);
+        }
+        self.create_from(&meta)
+    }
+
+    /// Create the resource on the server for a `meta` built by the caller, e.g. with
+    /// `UploadMeta::with_creation_path`
+    pub fn create_from(&self, meta: &UploadMeta) -> Result<UploadMeta, TusError> {
+        let meta = self.run(TusOp::Create, meta, None)?;
+        if meta.idempotency_key.is_some() {
+            return self.get_offset(&meta);
+        }
+        Ok(meta)
+    }
+
+    /// Declare the `total` length of an upload created with a deferred length
+    ///
+    /// Returns `TusError::LengthNotDeferred` if the length was declared on creation
+    pub fn set_length(&self, meta: &mut UploadMeta, total: usize) -> Result<(), TusError> {
+        if !meta.defer_length {
+            return Err(TusError::LengthNotDeferred);
+        }
+        let declared = UploadMeta {
+            status: UploadStatus::new(total, Some(meta.status.bytes_uploaded)),
+            ..meta.clone()
+        };
+        *meta = self.run(TusOp::DeclareLength, &declared, None)?;
+        Ok(())
+    }
+
+    /// Get offset for an existing resource
+    pub fn get_offset(&self, meta: &UploadMeta) -> Result<UploadMeta, TusError> {
+        self.run(TusOp::GetOffset, meta, None)
+    }
+
+    /// Resume an upload
+    pub fn resume(&self, meta: &UploadMeta) -> Result<UploadMeta, TusError> {
+        let meta = self.restore_checkpoint(meta)?;
+        meta.verify_file(self.options.verify_modified)?;
+        let mut source = ReaderSource::open(&meta.file_path)?;
+        self.send_chunks(meta, &mut source)
+    }
+
+    /// Resume the upload of `token`, e.g. created by another service, from `file`
+    ///
+    /// Fetches the offset from the server first. Returns `TusError::UnequalSizeError` if the
+    /// file isn't the size of the upload
+    pub fn resume_from_token(
+        &self,
+        token: &UploadToken,
+        file: &Path,
+    ) -> Result<UploadMeta, TusError> {
+        let meta = UploadMeta::from_token(file.to_path_buf(), token)?;
+        let meta = self.get_offset(&meta)?;
+        self.resume(&meta)
+    }
+
+    /// Resume an upload, reading the chunks from `source` rather than `meta.file_path`
+    pub fn resume_from(
+        &self,
+        meta: &UploadMeta,
+        source: &mut dyn ChunkSource,
+    ) -> Result<UploadMeta, TusError> {
+        let meta = self.restore_checkpoint(meta)?;
+        self.send_chunks(meta, source)
+    }
+
+    /// Resume an upload, reading the chunks from `reader` rather than opening `meta.file_path`
+    pub fn resume_with<R: Read + Seek>(
+        &self,
+        meta: &UploadMeta,
+        reader: R,
+    ) -> Result<UploadMeta, TusError> {
+        self.resume_from(meta, &mut ReaderSource::new(reader))
+    }
+
+    fn restore_checkpoint(&self, meta: &UploadMeta) -> Result<UploadMeta, TusError> {
+        let (meta, reconcile) = self.options.resume_point(meta)?;
+        if !reconcile {
+            return Ok(meta);
+        }
+        match self.get_offset(&meta) {
+            Err(error) if self.options.should_recreate(&error) => {
+                self.run(TusOp::Create, &meta.restarted(), None)
+            }
+            result => result,
+        }
+    }
+
+    fn send_chunks(
+        &self,
+        mut meta: UploadMeta,
+        source: &mut dyn ChunkSource,
+    ) -> Result<UploadMeta, TusError> {
+        let mut session = Session::new(self.options.initial_chunksize()?);
+        while !meta.upload_complete() {
+            meta = self.send_chunk(meta, source, &mut session)?;
+        }
+        let server_offset = match self.options.verify_size {
+            true => Some(self.get_offset(&meta)?.status.bytes_uploaded),
+            false => None,
+        };
+        session.complete(&self.options, meta, server_offset)
+    }
+
+    /// Upload the next chunk of `meta` from `source`
+    fn send_chunk(
+        &self,
+        mut meta: UploadMeta,
+        source: &mut dyn ChunkSource,
+        session: &mut Session,
+    ) -> Result<UploadMeta, TusError> {
+        let chunk = match session.next_chunk(&self.options, &self.chunksize, &meta, source)? {
+            ChunkStep::Upload(chunk) => chunk,
+            ChunkStep::DeclareLength(total, chunk) => {
+                self.set_length(&mut meta, total)?;
+                if chunk.is_empty() {
+                    self.options.save_checkpoint(&meta)?;
+                    return Ok(meta);
+                }
+                chunk
+            }
+        };
+        session.sending(&chunk);
+        let result = self.run(TusOp::Upload, &meta, Some(chunk));
+        // A failed recovery request counts like a failed chunk
+        let count = |error| self.options.count_error(&meta, error);
+        let meta = match session.handle_upload(&self.options, &meta, result)? {
+            Recovery::Acked(acked) => *acked,
+            Recovery::FetchOffset => self.get_offset(&meta).map_err(count)?,
+            Recovery::FetchAck => {
+                let fetched = self.get_offset(&meta).map_err(count)?;
+                session.acked(&self.options, &meta, fetched)?
+            }
+            Recovery::Recreate => self
+                .run(TusOp::Create, &meta.restarted(), None)
+                .map_err(count)?,
+        };
+        session.chunk_done(&self.options, &meta)?;
+        Ok(meta)
+    }
+
+    /// Upload a file
+    ///
+    /// Creates a resource on server and uploads the file, with the creation request when
+    /// `creation_with_upload` allows
+    #[allow(clippy::ptr_arg)]
+    pub fn upload(
+        &self,
+        file: &PathBuf,
+        host: &Url,
+        metadata: Option<HashMap<String, String>>,
+        custom_headers: Option<HashMap<String, String>>,
+    ) -> Result<UploadMeta, TusError> {
+        if self.creation_with_upload(file, host) {
+            return self.upload_with_creation(file, host, metadata, custom_headers);
+        }
+        let meta = self.create(file, host, metadata, custom_headers)?;
+        self.resume(&meta)
+    }
+
+    /// Whether to send the file with its creation request: `creation_with_upload` is set, it
+    /// fits in one chunk and the server supports Creation With Upload
+    fn creation_with_upload(&self, file: &Path, host: &Url) -> bool {
+        self.options.creates_with_upload(file)
+            && self
+                .get_server_info(host)
+                .is_ok_and(|info| info.extensions.contains(&TusExtension::CreationWithUpload))
+    }
+
+    /// Create the upload with the whole file as the body, resuming whatever the server didn't
+    /// store
+    fn upload_with_creation(
+        &self,
+        file: &Path,
+        host: &Url,
+        metadata: Option<HashMap<String, String>>,
+        custom_headers: Option<HashMap<String, String>>,
+    ) -> Result<UploadMeta, TusError> {
+        let started = Instant::now();
+        let (meta, body) = self
+            .options
+            .creation_with_body(file, host, metadata, custom_headers)?;
+        let bytes = body.len();
+        let meta = self.run(TusOp::Create, &meta, Some(body))?;
+        let mut meta = self.resume(&meta)?;
+        meta.stats = UploadStats::created_with_upload(bytes, started.elapsed(), &meta.stats);
+        Ok(meta)
+    }
+
+    /// Upload a file, taking any path and a url or string for the host
+    pub fn upload_file(
+        &self,
+        file: impl AsRef<Path>,
+        host: impl IntoUrl,
+        metadata: Option<HashMap<String, String>>,
+        custom_headers: Option<HashMap<String, String>>,
+    ) -> Result<UploadMeta, TusError> {
+        let host = parse_host(host)?;
+        let file = file.as_ref().to_path_buf();
+        self.upload(&file, &host, metadata, custom_headers)
+    }
+
+    /// Terminate upload and delete file
+    ///
+    /// An upload the server no longer knows counts as terminated, unless
+    /// `ClientOptions::terminate_treats_404_as_success` is unset
+    pub fn terminate(&self, meta: &UploadMeta) -> Result<(), TusError> {
+        let result = self.run(TusOp::Terminate, meta, None);
+        self.options.terminated(result)
+    }
+}
+
+/// Read the whole blocking `response`
+fn read_response(response: Response) -> FullResponse {
+    FullResponse {
+        status: response.status().as_u16(),
+        url: response.url().clone(),
+        headers: response.headers().clone(),
+        body: response.bytes().unwrap_or_default(),
+    }
+}
@@ -1,6 +1,17 @@
-use std::{io, num::ParseIntError};
+use std::{
+    collections::HashMap,
+    error::Error,
+    io,
+    num::ParseIntError,
+    path::{Path, PathBuf},
+};
 
-use crate::tus;
+use crate::tus::{
+    self,
+    headers::{to_headers, TusHeaders},
+    upload_meta::UploadMeta,
+};
+use reqwest::header::HeaderMap;
 
 /// Enumerates the errors which can occur during operation
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
@@ -8,11 +19,14 @@ pub enum TusError {
     /// UnexpectedStatusCode: ({0}) : {1}
     UnexpectedStatusCode(usize, String),
 
-    /// The file specified was not found by the server.
-    NotFoundError,
+    /// The file specified was not found by the server: {0}
+    NotFoundError(String),
 
-    /// Checksum mismatch error
-    ChecksumMismatch,
+    /// The upload no longer exists on the server, e.g. it expired or was terminated: {0}
+    Gone(String),
+
+    /// Checksum mismatch error: {0}
+    ChecksumMismatch(String),
 
     /// Invalid filename: {0}
     InvalidFilename(String),
@@ -41,17 +55,17 @@ pub enum TusError {
     /// String parsing error: {0}
     StringParseError(String),
 
-    /// The size of the specified file, and the file size reported by the server do not match.
-    UnequalSizeError,
+    /// The size of the specified file ({local}), and the file size reported by the server ({server}) do not match.
+    UnequalSizeError { local: usize, server: usize },
 
     /// Unable to read the file specified: {0}.
     FileReadError(String),
 
-    /// The `Client` tried to upload the file with an incorrect offset.
-    WrongUploadOffsetError,
+    /// The `Client` tried to upload the file with an incorrect offset: {0}
+    WrongUploadOffsetError(String),
 
-    /// The specified file is larger that what is supported by the server.
-    FileTooLarge,
+    /// The specified file is larger that what is supported by the server: {0}
+    FileTooLarge(String),
 
     /// An error occurred in the HTTP handler: {0}
     HttpHandlerError(tus::errors::TusAPIError),
@@ -70,6 +84,243 @@ pub enum TusError {
 
     /// Invalid to str
     ToStrError(reqwest::header::ToStrError),
+
+    /// Server error ({status}): {body}
+    ServerError {
+        status: u16,
+        headers: HashMap<String, String>,
+        body: String,
+    },
+
+    /// Invalid proxy: {0}
+    InvalidProxy(String),
+
+    /// Invalid client identity: {0}
+    InvalidIdentity(String),
+
+    /// Invalid root certificate: {0}
+    InvalidCertificate(String),
+
+    /// Invalid chunksize: {0} - must be at least 1 byte
+    InvalidChunkSize(usize),
+
+    /// Upload length is not deferred, it was declared when the upload was created
+    LengthNotDeferred,
+
+    /// Unsupported TUS version - server supports: {0:?}
+    UnsupportedVersion(Vec<String>),
+
+    /// File changed since the upload was created: {0}
+    FileChanged(String),
+
+    /// Invalid byte range: {0}
+    InvalidRange(String),
+
+    /// Invalid upload url: {0}
+    InvalidUrl(String),
+
+    /// Upload is locked by another request: {0}
+    Locked(String),
+
+    /// Too many errors during the upload: {count}
+    TooManyErrors {
+        count: usize,
+        /// the upload, to inspect or resume later
+        meta: Box<UploadMeta>,
+    },
+
+    /// Chunk failed, resume with the meta to keep counting errors: {source}
+    ChunkFailed {
+        source: Box<TusError>,
+        /// the upload with the failure counted in its `error_count`
+        meta: Box<UploadMeta>,
+    },
+
+    /// Upload was cancelled
+    Cancelled,
+
+    /// Server doesn't expose a checksum of the upload
+    VerificationUnsupported,
+
+    /// Invalid metadata key: {0:?} - must be non-empty ASCII without spaces or commas
+    InvalidMetadataKey(String),
+
+    /// Could not connect to the server: {0}
+    ConnectFailed(reqwest::Error),
+
+    /// Request timed out: {0}
+    Timeout(reqwest::Error),
+
+    /// TLS error: {0}
+    Tls(reqwest::Error),
+
+    /// Upload created on another host than the upload host: {0}
+    CrossHostLocation(String),
+
+    /// Upload changed since it was last seen, e.g. by another client: {0}
+    ResourceChanged(String),
+
+    /// File no longer exists: {path:?}
+    FileGone { path: PathBuf, source: io::Error },
+
+    /// Connection closed while sending the request: {0}
+    ConnectionReset(reqwest::Error),
+
+    /// Upload stalled, the server acknowledged chunks without advancing the offset past {0}
+    StalledUpload(usize),
+}
+
+impl TusError {
+    /// Classify a failed request, so callers can tell which failures are worth retrying
+    ///
+    /// A connection reset, aborted or closed before the whole response arrived, e.g. an HTTP/2
+    /// GOAWAY, becomes `ConnectionReset`, as the server may have stored part of the body.
+    /// Errors which aren't a timeout, TLS, connection failure or reset become `RequestError`
+    pub(crate) fn from_reqwest(error: reqwest::Error) -> Self {
+        let mut reset = false;
+        let mut source = error.source();
+        while let Some(cause) = source {
+            if cause.is::<native_tls::Error>() {
+                return TusError::Tls(error);
+            }
+            if let Some(io_error) = cause.downcast_ref::<io::Error>() {
+                reset |= matches!(
+                    io_error.kind(),
+                    io::ErrorKind::ConnectionReset
+                        | io::ErrorKind::ConnectionAborted
+                        | io::ErrorKind::BrokenPipe
+                );
+            }
+            // The server closed the connection before sending the whole response
+            if let Some(hyper_error) = cause.downcast_ref::<hyper::Error>() {
+                reset |= hyper_error.is_incomplete_message();
+            }
+            source = cause.source();
+        }
+        if error.is_timeout() {
+            TusError::Timeout(error)
+        } else if error.is_connect() {
+            TusError::ConnectFailed(error)
+        } else if reset {
+            TusError::ConnectionReset(error)
+        } else {
+            TusError::RequestError(format!("{error}"))
+        }
+    }
+
+    /// Map an error accessing the file at `path`, a missing file becomes `FileGone`
+    pub(crate) fn from_file_error(path: &Path, error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::NotFound => TusError::FileGone {
+                path: path.to_path_buf(),
+                source: error,
+            },
+            _ => TusError::IoError(error),
+        }
+    }
+
+    /// Map an unsuccessful response to the matching error, keeping the response `body` for
+    /// diagnostics
+    pub(crate) fn from_response(status: u16, headers: &HeaderMap, body: String) -> Self {
+        match status {
+            400 => TusError::BadRequest(body),
+            404 => TusError::NotFoundError(body),
+            409 => TusError::WrongUploadOffsetError(body),
+            410 => TusError::Gone(body),
+            // Tus-Resumable version not supported, the server lists the ones it does
+            412 => TusError::UnsupportedVersion(
+                TusHeaders::from(headers.clone())
+                    .supported_versions
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect(),
+            ),
+            413 => TusError::FileTooLarge(body),
+            // Another request, e.g. a concatenation, is using the upload
+            423 => TusError::Locked(body),
+            460 => TusError::ChecksumMismatch(body),
+            _ => TusError::ServerError {
+                status,
+                headers: to_headers(headers),
+                body,
+            },
+        }
+    }
+}
+
+/// Compares the variant and its payload, so tests can `assert_eq!` on errors
+///
+/// Variants wrapping opaque errors, `IoError`, `ReqwestError`, `ToStrError`, `ConnectFailed`,
+/// `Timeout`, `Tls` and `ConnectionReset`, are equal whenever the variants match.
+/// `TooManyErrors` compares only the `count`, `ChunkFailed` only the `source`, `FileGone` only
+/// the `path`
+impl PartialEq for TusError {
+    fn eq(&self, other: &Self) -> bool {
+        use TusError::*;
+        if std::mem::discriminant(self) != std::mem::discriminant(other) {
+            return false;
+        }
+        match (self, other) {
+            (UnexpectedStatusCode(a, b), UnexpectedStatusCode(c, d)) => a == c && b == d,
+            (NotFoundError(a), NotFoundError(b))
+            | (Gone(a), Gone(b))
+            | (ChecksumMismatch(a), ChecksumMismatch(b))
+            | (InvalidFilename(a), InvalidFilename(b))
+            | (MissingHeader(a), MissingHeader(b))
+            | (InvalidHeader(a), InvalidHeader(b))
+            | (InvalidHeaderValue(a), InvalidHeaderValue(b))
+            | (StringParseError(a), StringParseError(b))
+            | (FileReadError(a), FileReadError(b))
+            | (WrongUploadOffsetError(a), WrongUploadOffsetError(b))
+            | (FileTooLarge(a), FileTooLarge(b))
+            | (RequestError(a), RequestError(b))
+            | (BadRequest(a), BadRequest(b))
+            | (InvalidProxy(a), InvalidProxy(b))
+            | (InvalidIdentity(a), InvalidIdentity(b))
+            | (InvalidCertificate(a), InvalidCertificate(b))
+            | (FileChanged(a), FileChanged(b))
+            | (InvalidRange(a), InvalidRange(b))
+            | (InvalidUrl(a), InvalidUrl(b))
+            | (Locked(a), Locked(b))
+            | (InvalidMetadataKey(a), InvalidMetadataKey(b))
+            | (CrossHostLocation(a), CrossHostLocation(b))
+            | (ResourceChanged(a), ResourceChanged(b)) => a == b,
+            (ParsingError(a), ParsingError(b)) => a == b,
+            (HttpHandlerError(a), HttpHandlerError(b)) => a == b,
+            (InvalidChunkSize(a), InvalidChunkSize(b)) | (StalledUpload(a), StalledUpload(b)) => {
+                a == b
+            }
+            (UnsupportedVersion(a), UnsupportedVersion(b)) => a == b,
+            (
+                ServerError {
+                    status: a,
+                    headers: a_headers,
+                    body: a_body,
+                },
+                ServerError {
+                    status: b,
+                    headers: b_headers,
+                    body: b_body,
+                },
+            ) => a == b && a_headers == b_headers && a_body == b_body,
+            (
+                UnequalSizeError {
+                    local: a,
+                    server: b,
+                },
+                UnequalSizeError {
+                    local: c,
+                    server: d,
+                },
+            ) => a == c && b == d,
+            (TooManyErrors { count: a, .. }, TooManyErrors { count: b, .. }) => a == b,
+            (ChunkFailed { source: a, .. }, ChunkFailed { source: b, .. }) => a == b,
+            (FileGone { path: a, .. }, FileGone { path: b, .. }) => a == b,
+            // Variants without a payload, or whose payload can't be compared, e.g. io::Error
+            _ => true,
+        }
+    }
 }
 
 impl From<reqwest::header::ToStrError> for TusError {
@@ -0,0 +1,131 @@
+//! Handle to an upload running in the background, started with `Client::start`
+use crate::{
+    client::Client,
+    error::TusError,
+    tus::{upload_meta::UploadMeta, UploadStatus},
+};
+use tokio::{sync::watch, task::JoinHandle};
+
+/// Requested state of a background upload
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Control {
+    Run,
+    Pause,
+    Cancel,
+}
+
+/// An upload sent in the background
+///
+/// Pausing and cancelling take effect between chunks, a chunk being sent always completes.
+/// Dropping the handle cancels the upload after the current chunk
+pub struct Upload {
+    client: Client,
+    meta: UploadMeta,
+    control: watch::Sender<Control>,
+    status: watch::Receiver<UploadStatus>,
+    task: JoinHandle<Result<UploadMeta, TusError>>,
+}
+
+impl Upload {
+    /// Start sending the chunks of `meta`, which was already created on the server
+    pub(crate) fn spawn(client: Client, meta: UploadMeta) -> Self {
+        let (control, control_rx) = watch::channel(Control::Run);
+        let (status_tx, status) = watch::channel(meta.status.clone());
+        let task = tokio::spawn(run(client.clone(), meta.clone(), control_rx, status_tx));
+        Self {
+            client,
+            meta,
+            control,
+            status,
+            task,
+        }
+    }
+
+    /// The upload as created on the server
+    pub fn meta(&self) -> &UploadMeta {
+        &self.meta
+    }
+
+    /// Snapshot of the status after the latest confirmed chunk
+    pub fn status(&self) -> UploadStatus {
+        self.status.borrow().clone()
+    }
+
+    /// Stop sending once the current chunk completes
+    pub fn pause(&self) {
+        self.control.send_if_modified(|control| {
+            let running = *control == Control::Run;
+            if running {
+                *control = Control::Pause;
+            }
+            running
+        });
+    }
+
+    /// Continue a paused upload
+    pub fn resume(&self) {
+        self.control.send_if_modified(|control| {
+            let paused = *control == Control::Pause;
+            if paused {
+                *control = Control::Run;
+            }
+            paused
+        });
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.control.borrow() == Control::Pause
+    }
+
+    /// Stop the upload once the current chunk completes, then delete it from the server when
+    /// `terminate` is set
+    pub async fn cancel(self, terminate: bool) -> Result<(), TusError> {
+        self.control.send_replace(Control::Cancel);
+        let meta = match self.task.await {
+            // The upload may have completed before it saw the cancel
+            Ok(Ok(meta)) => meta,
+            _ => self
+                .meta
+                .with_bytes_uploaded(self.status.borrow().bytes_uploaded),
+        };
+        if terminate {
+            self.client.terminate(&meta).await?;
+        }
+        Ok(())
+    }
+
+    /// Wait for the upload to complete, returning `TusError::Cancelled` if it was cancelled
+    pub async fn wait(self) -> Result<UploadMeta, TusError> {
+        self.task
+            .await
+            .map_err(|e| TusError::RequestError(format!("{e}")))?
+    }
+}
+
+/// Send the chunks of `meta`, checking `control` between chunks
+async fn run(
+    client: Client,
+    meta: UploadMeta,
+    mut control: watch::Receiver<Control>,
+    status: watch::Sender<UploadStatus>,
+) -> Result<UploadMeta, TusError> {
+    let (mut meta, mut source, mut session) = client.start_session(&meta).await?;
+    while !meta.upload_complete() {
+        // A closed channel means the handle was dropped
+        let state = match control.has_changed() {
+            Ok(_) => match control.wait_for(|control| *control != Control::Pause).await {
+                Ok(state) => *state,
+                Err(_) => Control::Cancel,
+            },
+            Err(_) => Control::Cancel,
+        };
+        if state == Control::Cancel {
+            return Err(TusError::Cancelled);
+        }
+        meta = client.send_chunk(meta, &mut source, &mut session).await?;
+        status.send_replace(meta.status.clone());
+    }
+    let meta = client.finish_session(meta, session).await?;
+    status.send_replace(meta.status.clone());
+    Ok(meta)
+}
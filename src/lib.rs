@@ -1,4 +1,9 @@
+#[cfg(feature = "blocking")]
+pub mod blocking;
 pub mod client;
 pub mod error;
+pub mod handle;
+pub mod source;
+mod trace;
 pub mod tus;
 pub use client::*;
@@ -0,0 +1,160 @@
+//! Sources of the bytes uploaded by `Client::resume_from`
+use crate::error::TusError;
+use bytes::{Bytes, BytesMut};
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Provides the chunks of an upload
+pub trait ChunkSource {
+    /// Read up to `max_len` bytes starting at byte `offset` of the source
+    ///
+    /// Returns fewer bytes only at the end of the source
+    fn read_chunk(&mut self, offset: usize, max_len: usize) -> Result<Vec<u8>, TusError>;
+
+    /// Read the chunk as `Bytes`, sent as the request body without copying
+    ///
+    /// Defaults to `read_chunk`, sources holding their bytes in memory return a slice of them
+    fn read_bytes(&mut self, offset: usize, max_len: usize) -> Result<Bytes, TusError> {
+        self.read_chunk(offset, max_len).map(Bytes::from)
+    }
+}
+
+/// Chunks read from a seekable reader, e.g. a file or an in-memory `Cursor`
+///
+/// `read_bytes` reads into one buffer kept across chunks, which is reused once the previous
+/// chunk was sent and dropped
+pub struct ReaderSource<R> {
+    reader: R,
+    buffer: BytesMut,
+}
+
+impl<R: Read + Seek> ReaderSource<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: BytesMut::new(),
+        }
+    }
+}
+
+impl ReaderSource<BufReader<File>> {
+    /// Open the file at `path` as a source
+    ///
+    /// Returns `TusError::FileGone` if there is no file at `path`
+    pub fn open(path: &Path) -> Result<Self, TusError> {
+        let file = File::open(path).map_err(|e| TusError::from_file_error(path, e))?;
+        Ok(Self::new(BufReader::new(file)))
+    }
+}
+
+impl<R: Read + Seek> ChunkSource for ReaderSource<R> {
+    fn read_chunk(&mut self, offset: usize, max_len: usize) -> Result<Vec<u8>, TusError> {
+        self.reader.seek(SeekFrom::Start(offset as u64))?;
+        let mut chunk = vec![0; max_len];
+        let len = read_full(&mut self.reader, &mut chunk)?;
+        chunk.truncate(len);
+        Ok(chunk)
+    }
+
+    fn read_bytes(&mut self, offset: usize, max_len: usize) -> Result<Bytes, TusError> {
+        self.reader.seek(SeekFrom::Start(offset as u64))?;
+        // Reclaims the allocation of the previous chunk when nothing holds it anymore
+        self.buffer.clear();
+        self.buffer.resize(max_len, 0);
+        let len = read_full(&mut self.reader, &mut self.buffer)?;
+        self.buffer.truncate(len);
+        Ok(self.buffer.split().freeze())
+    }
+}
+
+impl ChunkSource for &[u8] {
+    fn read_chunk(&mut self, offset: usize, max_len: usize) -> Result<Vec<u8>, TusError> {
+        let start = offset.min(self.len());
+        let end = offset.saturating_add(max_len).min(self.len());
+        Ok(self[start..end].to_vec())
+    }
+}
+
+impl ChunkSource for Vec<u8> {
+    fn read_chunk(&mut self, offset: usize, max_len: usize) -> Result<Vec<u8>, TusError> {
+        self.as_slice().read_chunk(offset, max_len)
+    }
+}
+
+/// Chunks are slices sharing the buffer, so uploading from memory doesn't copy it
+impl ChunkSource for Bytes {
+    fn read_chunk(&mut self, offset: usize, max_len: usize) -> Result<Vec<u8>, TusError> {
+        self.as_ref().read_chunk(offset, max_len)
+    }
+
+    fn read_bytes(&mut self, offset: usize, max_len: usize) -> Result<Bytes, TusError> {
+        let start = offset.min(self.len());
+        let end = offset.saturating_add(max_len).min(self.len());
+        Ok(self.slice(start..end))
+    }
+}
+
+/// Chunks of the decompressed content of a gzip file, read without staging it on disk
+///
+/// Only reads forward, a chunk before the current position decompresses the file again from
+/// the start
+#[cfg(feature = "gzip")]
+pub struct GzipSource {
+    path: std::path::PathBuf,
+    decoder: flate2::read::MultiGzDecoder<BufReader<File>>,
+    position: usize,
+}
+
+#[cfg(feature = "gzip")]
+impl GzipSource {
+    /// Open the gzip file at `path` as a source
+    pub fn open(path: &Path) -> Result<Self, TusError> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            decoder: Self::decoder(path)?,
+            position: 0,
+        })
+    }
+
+    fn decoder(path: &Path) -> Result<flate2::read::MultiGzDecoder<BufReader<File>>, TusError> {
+        Ok(flate2::read::MultiGzDecoder::new(BufReader::new(
+            File::open(path)?,
+        )))
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl ChunkSource for GzipSource {
+    fn read_chunk(&mut self, offset: usize, max_len: usize) -> Result<Vec<u8>, TusError> {
+        if offset < self.position {
+            self.decoder = Self::decoder(&self.path)?;
+            self.position = 0;
+        }
+        let skip = (offset - self.position) as u64;
+        self.position +=
+            std::io::copy(&mut (&mut self.decoder).take(skip), &mut std::io::sink())? as usize;
+        let mut chunk = vec![0; max_len];
+        let len = read_full(&mut self.decoder, &mut chunk)?;
+        chunk.truncate(len);
+        self.position += len;
+        Ok(chunk)
+    }
+}
+
+/// Read until `buffer` is full or the end of the reader, so chunks keep their size even when
+/// the reader returns short reads
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
@@ -0,0 +1,72 @@
+//! `tracing` instrumentation for TUS operations, compiled away when the `tracing` feature is
+//! disabled
+#[cfg(feature = "tracing")]
+use crate::{
+    error::TusError,
+    tus::{ops::TusOp, upload_meta::UploadMeta},
+};
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+#[cfg(feature = "tracing")]
+use tracing::field;
+
+/// The span of an operation, see `op_span`, or `OpSpan::none()` for requests outside one
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::Span as OpSpan;
+
+/// Nothing to record into without the `tracing` feature
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct OpSpan;
+
+#[cfg(not(feature = "tracing"))]
+impl OpSpan {
+    pub(crate) fn none() -> Self {
+        OpSpan
+    }
+}
+
+/// Span covering a single operation, `status_code` and `duration_ms` are recorded once known
+#[cfg(feature = "tracing")]
+pub(crate) fn op_span(op: &TusOp, metadata: &UploadMeta, body: Option<&[u8]>) -> OpSpan {
+    tracing::info_span!(
+        "tus_op",
+        op = ?op,
+        url = %op.url_for_meta(metadata),
+        offset = metadata.status.bytes_uploaded,
+        bytes_sent = body.map_or(0, <[u8]>::len),
+        status_code = field::Empty,
+        duration_ms = field::Empty,
+        retries = field::Empty,
+    )
+}
+
+/// Record a retry of the operation of `_span` after `_error`
+#[inline]
+pub(crate) fn record_retry(_span: &OpSpan, _attempt: usize, _error: &crate::error::TusError) {
+    #[cfg(feature = "tracing")]
+    {
+        _span.record("retries", _attempt);
+        tracing::warn!(
+            parent: _span,
+            attempt = _attempt,
+            error = %_error,
+            "retrying tus operation"
+        );
+    }
+}
+
+/// Record the response status on the span of its operation
+#[inline]
+pub(crate) fn record_status(_span: &OpSpan, _status: u16) {
+    #[cfg(feature = "tracing")]
+    _span.record("status_code", _status);
+}
+
+/// Record the duration of the operation and its error, if any
+#[cfg(feature = "tracing")]
+pub(crate) fn finish<T>(span: &OpSpan, started: Instant, result: &Result<T, TusError>) {
+    span.record("duration_ms", started.elapsed().as_millis() as u64);
+    if let Err(error) = result {
+        span.in_scope(|| tracing::error!(error = %error, "tus operation failed"));
+    }
+}
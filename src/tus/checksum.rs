@@ -0,0 +1,64 @@
+use crate::error::TusError;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::{fmt, io::Read, str::FromStr};
+
+/// Checksum algorithms the client can compute, named as in the tus Checksum extension
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgorithm {
+    Sha1,
+    Sha256,
+    Md5,
+}
+
+impl ChecksumAlgorithm {
+    /// Stream `reader` to the end, returning the raw digest
+    pub fn digest<R: Read>(&self, reader: R) -> Result<Vec<u8>, TusError> {
+        match self {
+            Self::Sha1 => digest::<Sha1, R>(reader),
+            Self::Sha256 => digest::<Sha256, R>(reader),
+            Self::Md5 => digest::<md5::Md5, R>(reader),
+        }
+    }
+
+    /// Stream `reader` to the end, returning the digest as lowercase hex
+    pub fn hex_digest<R: Read>(&self, reader: R) -> Result<String, TusError> {
+        let digest = self.digest(reader)?;
+        Ok(digest.iter().map(|b| format!("{b:02x}")).collect())
+    }
+}
+
+fn digest<D: Digest, R: Read>(mut reader: R) -> Result<Vec<u8>, TusError> {
+    let mut hasher = D::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let count = reader.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[..count]);
+    }
+    Ok(hasher.finalize().to_vec())
+}
+
+impl fmt::Display for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Sha1 => write!(f, "sha1"),
+            Self::Sha256 => write!(f, "sha256"),
+            Self::Md5 => write!(f, "md5"),
+        }
+    }
+}
+
+impl FromStr for ChecksumAlgorithm {
+    type Err = TusError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(&format!("\"{}\"", s.trim().to_lowercase())).map_err(|_| {
+            TusError::StringParseError(format!("Invalid ChecksumAlgorithm String: {s}"))
+        })
+    }
+}
@@ -1,5 +1,5 @@
 /// The errors a TusAPI can return
-#[derive(thiserror::Error, displaydoc::Display, Debug)]
+#[derive(thiserror::Error, displaydoc::Display, Debug, PartialEq)]
 pub enum TusAPIError {
     /// Underlying Error
     UnderlyingError,
@@ -1,59 +1,106 @@
-use base64::Engine;
 use reqwest::header::{HeaderMap, HeaderValue};
 use std::collections::HashMap;
 
-use super::{FromStr, TusExtension};
+use super::{metadata::UploadMetadata, FromStr, TusExtension, TusVersion, UploadConcat};
 
 /// Indicates a byte offset withing a resource.
-pub const UPLOAD_OFFSET: &'static str = "upload-offset";
+pub const UPLOAD_OFFSET: &str = "upload-offset";
 
 /// Indicates the size of the entire upload in bytes.
-pub const UPLOAD_LENGTH: &'static str = "upload-length";
+pub const UPLOAD_LENGTH: &str = "upload-length";
 
 /// A comma-separated list of protocol versions supported by the server.
-pub const TUS_VERSION: &'static str = "tus-version";
+pub const TUS_VERSION: &str = "tus-version";
 
 /// The version of the protocol used by the client or the server.
-pub const TUS_RESUMABLE: &'static str = "tus-resumable";
+pub const TUS_RESUMABLE: &str = "tus-resumable";
 
 /// A comma-separated list of the extensions supported by the server.
-pub const TUS_EXTENSION: &'static str = "tus-extension";
+pub const TUS_EXTENSION: &str = "tus-extension";
 
 /// Integer indicating the maximum allowed size of an entire upload in bytes.
-pub const TUS_MAX_SIZE: &'static str = "tus-max-size";
+pub const TUS_MAX_SIZE: &str = "tus-max-size";
 
 /// Checksum algorithms supported by the server
-pub const TUS_CHECKSUM_ALGO: &'static str = "tus-checksum-algorithm";
+pub const TUS_CHECKSUM_ALGO: &str = "tus-checksum-algorithm";
 
 /// Use this header if its environment does not support the PATCH or DELETE methods.
-pub const X_HTTP_METHOD_OVERRIDE: &'static str = "x-http-method-override";
+pub const X_HTTP_METHOD_OVERRIDE: &str = "x-http-method-override";
 
 /// Use this header if its environment does not support the PATCH or DELETE methods.
-pub const CONTENT_TYPE: &'static str = "content-type";
+pub const CONTENT_TYPE: &str = "content-type";
+
+/// Sent explicitly on PATCH requests, as some servers and proxies reject chunked bodies
+pub const CONTENT_LENGTH: &str = "content-length";
 
 /// Use this header if its environment does not support the PATCH or DELETE methods.
-pub const UPLOAD_DEFER_LENGTH: &'static str = "upload-defer-length";
+pub const UPLOAD_DEFER_LENGTH: &str = "upload-defer-length";
 
 /// Use this header if its environment does not support the PATCH or DELETE methods.
-pub const UPLOAD_METADATA: &'static str = "upload-metadata";
+pub const UPLOAD_METADATA: &str = "upload-metadata";
+
+/// Marks an upload as partial, or final with the list of partial uploads to concatenate.
+pub const UPLOAD_CONCAT: &str = "upload-concat";
+
+/// Client generated key identifying a creation request, so a repeated request can return the
+/// existing upload
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+/// Checksum as "<algorithm> <base64 digest>", some servers return the checksum of the whole
+/// upload in it on HEAD responses
+pub const UPLOAD_CHECKSUM: &str = "upload-checksum";
+
+/// Version of the upload resource, captured to make conditional requests
+pub const ETAG: &str = "etag";
+
+/// Only apply a PATCH if the upload still has the given `ETag`
+pub const IF_MATCH: &str = "if-match";
 
 /// Use this header when creating an upload to get the location of the upload on the server
-pub const TUS_LOCATION: &'static str = "location";
+pub const TUS_LOCATION: &str = "location";
+
+/// Headers checked in order for the url of a created upload, for gateways which rename
+/// `Location`
+pub const LOCATION_HEADERS: &[&str] = &[TUS_LOCATION, "tus-location"];
 
 /// An alias for `HashMap<String, String>`, which represents a set of HTTP headers and their values.
 pub type Headers = HashMap<String, String>;
 
-pub fn default_headers() -> Headers {
+/// Headers sent with every request, `version` is sent as the `Tus-Resumable` value
+pub fn default_headers(version: &str) -> Headers {
     let mut map = Headers::new();
-    map.insert(String::from(TUS_RESUMABLE), String::from("1.0.0"));
+    map.insert(String::from(TUS_RESUMABLE), String::from(version));
     map
 }
 
+/// Collect a `HeaderMap` into `Headers`, dropping values which aren't valid strings
+pub fn to_headers(map: &HeaderMap) -> Headers {
+    map.iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+/// Decode an `Upload-Metadata` value of comma separated `key base64(value)` pairs
+///
+/// Keys without a value decode to an empty string, pairs with invalid base64 are skipped. Use
+/// `UploadMetadata::decode` to tell bare keys apart from empty values
+pub fn decode_metadata(value: &str) -> HashMap<String, String> {
+    UploadMetadata::decode(value)
+        .into_iter()
+        .map(|(key, value)| (key, value.unwrap_or_default()))
+        .collect()
+}
+
+/// TUS protocol headers parsed from a response
+///
+/// Missing or malformed headers are `None`, so headers from any transport can be parsed with
+/// `TusHeaders::from_headers`
+#[derive(Debug, Clone)]
 pub struct TusHeaders {
     pub offset: Option<usize>,
     pub upload_length: Option<usize>,
-    pub version: Option<String>,
-    pub supported_versions: Option<Vec<String>>,
+    pub version: Option<TusVersion>,
+    pub supported_versions: Option<Vec<TusVersion>>,
     pub resumable: Option<String>,
     pub extensions: Option<Vec<TusExtension>>,
     pub max_size: Option<usize>,
@@ -61,61 +108,56 @@ pub struct TusHeaders {
     pub upload_metadata: Option<HashMap<String, String>>,
     pub upload_defer_length: Option<usize>,
     pub location: Option<String>,
+    pub concat: Option<UploadConcat>,
+    pub etag: Option<String>,
 }
 
 impl From<HeaderMap> for TusHeaders {
     fn from(value: HeaderMap) -> Self {
-        let headers: HashMap<String, String> = value
-            .iter()
-            .map(|(k, v)| (k.to_string(), v.to_string()))
-            .collect();
-        let version: Option<String> = headers.get(TUS_RESUMABLE).map(|v| v.to_string());
+        Self::from_headers(&value)
+    }
+}
+
+impl TusHeaders {
+    /// Parse the TUS headers of a response
+    pub fn from_headers(value: &HeaderMap) -> Self {
+        let headers = to_headers(value);
+        let version: Option<TusVersion> = headers.get(TUS_RESUMABLE).map(|v| v.as_str().into());
         let max_size: Option<usize> = headers
             .get(TUS_MAX_SIZE)
-            .map(|v| v.parse::<usize>().unwrap().into());
+            .and_then(|v| v.parse::<usize>().ok());
         let upload_defer_length = headers
             .get(UPLOAD_DEFER_LENGTH)
-            .map(|v| v.parse::<usize>().unwrap().into());
+            .and_then(|v| v.parse::<usize>().ok());
         let extensions: Option<Vec<TusExtension>> = headers.get(TUS_EXTENSION).map(|string| {
             string
                 .split(',')
-                .filter_map(|s| TusExtension::from_str(s).ok())
+                .filter_map(|s| TusExtension::from_str(s.trim()).ok())
                 .collect()
         });
-        let supported_versions: Option<Vec<String>> = headers
+        let supported_versions: Option<Vec<TusVersion>> = headers
             .get(TUS_VERSION)
-            .map(|v| v.split(',').map(String::from).collect::<Vec<String>>());
-        let checksum_algorithms: Option<Vec<String>> = headers
-            .get(TUS_CHECKSUM_ALGO)
-            .map(|value| value.split(',').map(String::from).collect::<Vec<String>>());
+            .map(|v| v.split(',').map(TusVersion::from).collect());
+        let checksum_algorithms: Option<Vec<String>> =
+            headers.get(TUS_CHECKSUM_ALGO).map(|value| {
+                value
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .collect::<Vec<String>>()
+            });
         let offset = headers
             .get(UPLOAD_OFFSET)
-            .map_or(None, |v| str::parse::<usize>(&v).ok());
+            .and_then(|v| str::parse::<usize>(v).ok());
         let upload_length = headers
             .get(UPLOAD_LENGTH)
-            .map_or(None, |v| str::parse::<usize>(&v).ok());
+            .and_then(|v| str::parse::<usize>(v).ok());
         let resumable = headers.get(TUS_RESUMABLE).map(|s| s.to_owned());
         let location = headers.get(TUS_LOCATION).map(|s| s.to_owned());
-        let upload_metadata = headers
-            .get(UPLOAD_METADATA)
-            .map_or(None, |list| {
-                base64::engine::general_purpose::STANDARD.decode(list).ok()
-            })
-            .map(|decoded| {
-                String::from_utf8(decoded).unwrap().split(";").fold(
-                    HashMap::new(),
-                    |mut acc, key_val| {
-                        let mut parts = key_val.splitn(2, ':');
-                        if let Some(key) = parts.next() {
-                            acc.insert(
-                                String::from(key),
-                                String::from(parts.next().unwrap_or_default()),
-                            );
-                        }
-                        acc
-                    },
-                )
-            });
+        let upload_metadata = headers.get(UPLOAD_METADATA).map(|v| decode_metadata(v));
+        let concat = headers
+            .get(UPLOAD_CONCAT)
+            .and_then(|v| UploadConcat::from_str(v).ok());
+        let etag = headers.get(ETAG).map(|s| s.to_owned());
 
         Self {
             offset,
@@ -129,6 +171,8 @@ impl From<HeaderMap> for TusHeaders {
             checksum_algorithms,
             upload_metadata,
             location,
+            concat,
+            etag,
         }
     }
 }
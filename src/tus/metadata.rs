@@ -0,0 +1,131 @@
+use crate::error::TusError;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Builder for the `Upload-Metadata` header value
+///
+/// Pairs are encoded as "key base64(value)" separated by commas, keys without a value are sent
+/// bare. Keys must be non-empty ASCII without spaces or commas, and are encoded in sorted order
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadMetadata {
+    entries: BTreeMap<String, Option<Vec<u8>>>,
+}
+
+impl UploadMetadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set `key` to `value`, replacing any previous value
+    pub fn set(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.entries
+            .insert(key.into(), Some(value.into().into_bytes()));
+        self
+    }
+
+    /// Set `key` to binary `value`, which is base64 encoded verbatim
+    pub fn set_bytes(mut self, key: impl Into<String>, value: impl Into<Vec<u8>>) -> Self {
+        self.entries.insert(key.into(), Some(value.into()));
+        self
+    }
+
+    /// Set `key` without a value, e.g. a flag like "is_confidential"
+    pub fn set_bare(mut self, key: impl Into<String>) -> Self {
+        self.entries.insert(key.into(), None);
+        self
+    }
+
+    /// The value of `key`, `Some(None)` for a bare key
+    ///
+    /// `None` if the value isn't valid UTF-8, use `UploadMetadata::get_bytes` for binary values
+    pub fn get(&self, key: &str) -> Option<Option<&str>> {
+        match self.get_bytes(key)? {
+            Some(value) => std::str::from_utf8(value).ok().map(Some),
+            None => Some(None),
+        }
+    }
+
+    /// The binary value of `key`, `Some(None)` for a bare key
+    pub fn get_bytes(&self, key: &str) -> Option<Option<&[u8]>> {
+        self.entries.get(key).map(Option::as_deref)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Check every key can be sent, returning `TusError::InvalidMetadataKey` for the first
+    /// which can't
+    pub fn validate(&self) -> Result<(), TusError> {
+        match self
+            .entries
+            .keys()
+            .find(|key| key.is_empty() || key.chars().any(|c| !c.is_ascii_graphic() || c == ','))
+        {
+            Some(key) => Err(TusError::InvalidMetadataKey(key.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Encode as an `Upload-Metadata` header value
+    pub fn encode(&self) -> String {
+        self.entries
+            .iter()
+            .map(|(key, value)| match value {
+                Some(value) => format!(
+                    "{} {}",
+                    key,
+                    base64::engine::general_purpose::STANDARD.encode(value)
+                ),
+                None => key.clone(),
+            })
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+
+    /// Decode an `Upload-Metadata` header value, bare keys decode to `None`
+    ///
+    /// Values are decoded as UTF-8, replacing invalid sequences, pairs with invalid base64 are
+    /// skipped
+    pub fn decode(header: &str) -> HashMap<String, Option<String>> {
+        Self::decode_bytes(header)
+            .into_iter()
+            .map(|(key, value)| {
+                let value = value.map(|v| String::from_utf8_lossy(&v).to_string());
+                (key, value)
+            })
+            .collect()
+    }
+
+    /// Decode an `Upload-Metadata` header value keeping the raw value bytes, bare keys decode
+    /// to `None`
+    ///
+    /// Pairs with invalid base64 are skipped
+    pub fn decode_bytes(header: &str) -> HashMap<String, Option<Vec<u8>>> {
+        header
+            .split(',')
+            .map(str::trim_start)
+            .filter(|pair| !pair.is_empty())
+            .filter_map(|pair| match pair.split_once(' ') {
+                Some((key, value)) => base64::engine::general_purpose::STANDARD
+                    .decode(value.trim())
+                    .ok()
+                    .map(|decoded| (key.to_string(), Some(decoded))),
+                None => Some((pair.to_string(), None)),
+            })
+            .collect()
+    }
+}
+
+/// Empty values become bare keys
+impl From<HashMap<String, String>> for UploadMetadata {
+    fn from(map: HashMap<String, String>) -> Self {
+        map.into_iter().fold(Self::new(), |metadata, (key, value)| {
+            match value.is_empty() {
+                true => metadata.set_bare(key),
+                false => metadata.set(key, value),
+            }
+        })
+    }
+}
@@ -1,18 +1,116 @@
+pub mod checksum;
 pub mod errors;
 pub mod headers;
 pub mod http;
+pub mod metadata;
 pub mod ops;
+pub mod token;
 pub mod upload_meta;
 
+use std::convert::Infallible;
 use std::str::FromStr;
 
 use reqwest::header::HeaderMap;
 use serde;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+use url::Url;
 
 use crate::error::TusError;
 use crate::tus::headers::TusHeaders;
 
+/// TUS protocol version the client speaks, sent in `Tus-Resumable` unless
+/// `ClientOptions::tus_version` says otherwise
+pub const TUS_PROTOCOL_VERSION: &str = "1.0.0";
+
+/// TUS protocol versions supported by the client, in order of preference
+pub const SUPPORTED_VERSIONS: &[&str] = &[TUS_PROTOCOL_VERSION];
+
+/// A TUS protocol version, as sent in `Tus-Resumable` and listed in `Tus-Version`
+///
+/// Serialized as the version string, so versions the client doesn't know are kept as `Other`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[serde(from = "String", into = "String")]
+pub enum TusVersion {
+    /// 1.0.0, the current protocol
+    V1_0_0,
+
+    /// 0.2.2, the last draft before 1.0.0
+    V0_2_2,
+
+    /// Any other version
+    Other(String),
+}
+
+impl TusVersion {
+    /// The version string, e.g. "1.0.0", as sent in headers
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::V1_0_0 => TUS_PROTOCOL_VERSION,
+            Self::V0_2_2 => "0.2.2",
+            Self::Other(version) => version,
+        }
+    }
+}
+
+/// 1.0.0, the current protocol
+impl Default for TusVersion {
+    fn default() -> Self {
+        Self::V1_0_0
+    }
+}
+
+impl fmt::Display for TusVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Parses a version string, surrounding whitespace is ignored
+impl FromStr for TusVersion {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim() {
+            TUS_PROTOCOL_VERSION => Self::V1_0_0,
+            "0.2.2" => Self::V0_2_2,
+            other => Self::Other(other.to_string()),
+        })
+    }
+}
+
+impl From<&str> for TusVersion {
+    fn from(value: &str) -> Self {
+        let Ok(version) = value.parse();
+        version
+    }
+}
+
+impl From<String> for TusVersion {
+    fn from(value: String) -> Self {
+        value.as_str().into()
+    }
+}
+
+impl From<TusVersion> for String {
+    fn from(value: TusVersion) -> Self {
+        value.as_str().to_string()
+    }
+}
+
+impl PartialEq<str> for TusVersion {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for TusVersion {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UploadStatus {
     /// total range uploaded
@@ -20,6 +118,10 @@ pub struct UploadStatus {
 
     /// total size of file in bytes
     pub size: usize,
+
+    /// the server doesn't know the length of the upload yet, `size` is 0
+    #[serde(default)]
+    pub length_deferred: bool,
 }
 
 impl UploadStatus {
@@ -27,20 +129,212 @@ impl UploadStatus {
         UploadStatus {
             size,
             bytes_uploaded: bytes_uploaded.unwrap_or(0),
+            length_deferred: false,
         }
     }
+
+    /// Parse the status from the headers of a HEAD response for an upload
+    ///
+    /// A missing `Upload-Length` is treated as a deferred length
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, TusError> {
+        let headers = TusHeaders::from_headers(headers);
+        let offset = headers
+            .offset
+            .ok_or(TusError::MissingHeader(headers::UPLOAD_OFFSET.to_owned()))?;
+        Ok(match headers.upload_length {
+            Some(size) => UploadStatus::new(size, Some(offset)),
+            None => UploadStatus {
+                length_deferred: true,
+                ..UploadStatus::new(0, Some(offset))
+            },
+        })
+    }
+}
+
+/// Timings of a single `upload`/`resume` session, useful to tune `chunksize`
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct UploadStats {
+    /// bytes sent in PATCH request bodies
+    pub bytes_sent: usize,
+
+    /// number of PATCH requests sent
+    pub patch_requests: usize,
+
+    /// wall time spent sending the chunks
+    pub elapsed: Duration,
+
+    /// how the upload was sent
+    pub strategy: UploadStrategy,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// How `Client::upload` sent a file
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum UploadStrategy {
+    /// Empty creation request, then PATCH requests
+    #[default]
+    CreateThenPatch,
+
+    /// The file was sent with the creation request, using the Creation With Upload extension
+    CreationWithUpload,
+}
+
+impl UploadStats {
+    /// Average throughput in bytes per second, 0 if no time elapsed
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.bytes_sent as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Combine the stats of two sessions, e.g. an upload and a later resume
+    ///
+    /// Keeps the strategy of `self`, which started the upload
+    pub fn merge(&self, other: &UploadStats) -> UploadStats {
+        UploadStats {
+            bytes_sent: self.bytes_sent + other.bytes_sent,
+            patch_requests: self.patch_requests + other.patch_requests,
+            elapsed: self.elapsed + other.elapsed,
+            strategy: self.strategy,
+        }
+    }
+
+    /// The stats of an upload which sent `bytes` with its creation request then `resumed` the
+    /// rest, `elapsed` in total
+    pub(crate) fn created_with_upload(
+        bytes: usize,
+        elapsed: Duration,
+        resumed: &UploadStats,
+    ) -> UploadStats {
+        UploadStats {
+            bytes_sent: bytes,
+            elapsed,
+            strategy: UploadStrategy::CreationWithUpload,
+            ..UploadStats::default()
+        }
+        .merge(&UploadStats {
+            elapsed: Duration::ZERO,
+            ..*resumed
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct TusServerInfo {
-    pub version: Option<String>,
+    pub version: Option<TusVersion>,
     pub max_size: Option<usize>,
     pub extensions: Vec<TusExtension>,
-    pub supported_versions: Vec<String>,
+    pub supported_versions: Vec<TusVersion>,
     pub supported_checksum_algorithms: Option<Vec<String>>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl TusServerInfo {
+    /// Parse the server info from the headers of an OPTIONS response
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        TusHeaders::from_headers(headers).into()
+    }
+
+    /// Whether the server accepts an upload of `file_size` bytes, always true without a
+    /// `Tus-Max-Size`
+    pub fn can_upload(&self, file_size: usize) -> bool {
+        self.max_size.is_none_or(|max_size| file_size <= max_size)
+    }
+
+    /// Bytes to spare under the server's `Tus-Max-Size` after an upload of `file_size` bytes
+    ///
+    /// None when the server doesn't limit the size, 0 when the upload doesn't fit
+    pub fn remaining_capacity(&self, file_size: usize) -> Option<usize> {
+        self.max_size
+            .map(|max_size| max_size.saturating_sub(file_size))
+    }
+
+    /// Check the server accepts an upload of `file_size` bytes
+    ///
+    /// Returns `TusError::FileTooLarge` with the limit otherwise
+    pub fn check_size(&self, file_size: usize) -> Result<(), TusError> {
+        match self.max_size {
+            Some(max_size) if file_size > max_size => Err(TusError::FileTooLarge(format!(
+                "file is {file_size} bytes, server accepts at most {max_size}"
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Select the first of the `preferred` checksum algorithms which the server supports,
+    /// e.g. `&["sha256", "sha1", "md5"]`, compared case insensitively
+    ///
+    /// None when the server advertises no `Tus-Checksum-Algorithm`, or none of `preferred`
+    pub fn best_checksum(&self, preferred: &[&str]) -> Option<String> {
+        let supported = self.supported_checksum_algorithms.as_ref()?;
+        preferred
+            .iter()
+            .find(|algorithm| supported.iter().any(|s| s.eq_ignore_ascii_case(algorithm)))
+            .map(|algorithm| algorithm.to_string())
+    }
+
+    /// Select the first of the `preferred` versions which is also supported by the server
+    ///
+    /// Falls back to the server's `Tus-Resumable` version when no `Tus-Version` list is
+    /// advertised, and to the first `preferred` version when the server advertises neither.
+    pub fn negotiate_version(&self, preferred: &[&str]) -> Result<TusVersion, TusError> {
+        let server_versions: Vec<TusVersion> = if self.supported_versions.is_empty() {
+            self.version.iter().cloned().collect()
+        } else {
+            self.supported_versions.clone()
+        };
+        let unsupported = || {
+            TusError::UnsupportedVersion(server_versions.iter().map(|v| v.to_string()).collect())
+        };
+        if server_versions.is_empty() {
+            return preferred
+                .first()
+                .map(|v| TusVersion::from(*v))
+                .ok_or_else(unsupported);
+        }
+        preferred
+            .iter()
+            .map(|v| TusVersion::from(*v))
+            .find(|v| server_versions.contains(v))
+            .ok_or_else(unsupported)
+    }
+}
+
+/// One line summary for logs, e.g. "tus 1.0.0, max 5GB, ext: creation,expiration"
+impl fmt::Display for TusServerInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "tus {version}")?,
+            None => write!(f, "tus unknown")?,
+        }
+        if let Some(max_size) = self.max_size {
+            write!(f, ", max {}", human_size(max_size))?;
+        }
+        let extensions: Vec<String> = self.extensions.iter().map(|e| e.to_string()).collect();
+        match extensions.is_empty() {
+            true => write!(f, ", ext: none"),
+            false => write!(f, ", ext: {}", extensions.join(",")),
+        }
+    }
+}
+
+/// Format a number of bytes in the largest whole unit, e.g. "5GB" or "1.5MB"
+fn human_size(bytes: usize) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match size.fract() == 0.0 {
+        true => format!("{}{}", size, UNITS[unit]),
+        false => format!("{:.1}{}", size, UNITS[unit]),
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub enum TusExtension {
     Creation,
@@ -51,6 +345,60 @@ pub enum TusExtension {
     CreationDeferLength,
 }
 
+/// Role of an upload in the Concatenation extension
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum UploadConcat {
+    /// Part of a file, to be concatenated into a final upload
+    Partial,
+
+    /// Concatenation of the partial uploads at these urls, in order
+    Final(Vec<Url>),
+}
+
+impl fmt::Display for UploadConcat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Partial => write!(f, "partial"),
+            Self::Final(urls) => {
+                let urls: Vec<&str> = urls.iter().map(Url::as_str).collect();
+                write!(f, "final;{}", urls.join(" "))
+            }
+        }
+    }
+}
+
+/// Parses an `Upload-Concat` value, `partial` or `final;` followed by space separated urls
+impl FromStr for UploadConcat {
+    type Err = TusError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || TusError::StringParseError(format!("Invalid Upload-Concat String: {s}"));
+        match s.trim().split_once(';') {
+            None if s.trim() == "partial" => Ok(Self::Partial),
+            Some(("final", urls)) => urls
+                .split_whitespace()
+                .map(|url| Url::parse(url).map_err(|_| invalid()))
+                .collect::<Result<_, _>>()
+                .map(Self::Final),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for TusExtension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Creation => "creation",
+            Self::CreationWithUpload => "creation-with-upload",
+            Self::Terminate => "terminate",
+            Self::Expiration => "expiration",
+            Self::Concatenation => "concatenation",
+            Self::CreationDeferLength => "creation-defer-length",
+        };
+        write!(f, "{name}")
+    }
+}
+
 impl FromStr for TusExtension {
     type Err = TusError;
 
@@ -62,24 +410,23 @@ impl FromStr for TusExtension {
 
 impl From<TusHeaders> for TusServerInfo {
     fn from(headers: TusHeaders) -> Self {
-        let version: Option<String> = headers.version;
+        let version: Option<TusVersion> = headers.version;
         let max_size: Option<usize> = headers.max_size;
         let extensions: Vec<TusExtension> = headers.extensions.unwrap_or_default();
-        let supported_versions: Vec<String> = headers.supported_versions.unwrap_or_default();
+        let supported_versions: Vec<TusVersion> = headers.supported_versions.unwrap_or_default();
         let supported_checksum_algorithms: Option<Vec<String>> = headers.checksum_algorithms;
-        return Self {
+        Self {
             version,
             max_size,
             extensions,
             supported_versions,
             supported_checksum_algorithms,
-        };
+        }
     }
 }
 
 impl From<HeaderMap> for TusServerInfo {
     fn from(value: HeaderMap) -> Self {
-        let headers: TusHeaders = value.into();
-        headers.into()
+        Self::from_headers(&value)
     }
 }
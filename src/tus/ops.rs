@@ -1,5 +1,5 @@
-use crate::{error::TusError, tus};
-use reqwest::Response;
+use crate::{client::ClientOptions, error::TusError, tus};
+use reqwest::header::HeaderMap;
 use serde;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -9,8 +9,9 @@ use url::Url;
 use super::headers::TusHeaders;
 use super::http::TusHttpMethod;
 use super::upload_meta::UploadMeta;
+use super::{UploadConcat, UploadStatus};
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
 pub(crate) enum TusOp {
     // ************
@@ -34,6 +35,9 @@ pub(crate) enum TusOp {
 
     /// End upload and delete file
     Terminate,
+
+    /// Declare the length of an upload created with a deferred length
+    DeclareLength,
 }
 
 impl From<TusOp> for TusHttpMethod {
@@ -49,27 +53,102 @@ impl TusOp {
 
             // all patch requests must contain
             // "Content-Type": "application/offset+octet-stream"
-            TusOp::Upload => TusHttpMethod::Patch,
+            TusOp::Upload | TusOp::DeclareLength => TusHttpMethod::Patch,
             TusOp::Create => TusHttpMethod::Post, // empty post request
             TusOp::Terminate => TusHttpMethod::Delete,
         }
     }
 
-    pub fn headers(&self, metadata: &UploadMeta) -> Result<HashMap<String, String>, TusError> {
-        let mut headers = tus::headers::default_headers();
-        let data = metadata.data64()?;
-        headers.insert(tus::headers::UPLOAD_METADATA.to_owned(), data);
-        if let Some(custom_headers) = &metadata.custom_headers {
-            headers.extend(custom_headers.clone());
+    /// Headers for the operation's request
+    ///
+    /// `Upload-Metadata` is only sent on `Create`, and on PATCH requests when
+    /// `options.metadata_on_patch` is set, never when `options.send_metadata` is unset
+    pub fn headers(
+        &self,
+        metadata: &UploadMeta,
+        body: Option<&[u8]>,
+        options: &ClientOptions,
+    ) -> Result<HashMap<String, String>, TusError> {
+        // Custom headers go first so protocol headers always win, names are lowercased to
+        // match the protocol header names
+        let mut headers: HashMap<String, String> = metadata
+            .custom_headers
+            .iter()
+            .flatten()
+            .map(|(k, v)| (k.to_lowercase(), v.clone()))
+            .collect();
+        headers.extend(tus::headers::default_headers(metadata.version.as_str()));
+        let send_metadata = match self {
+            TusOp::Create => true,
+            TusOp::Upload | TusOp::DeclareLength => options.metadata_on_patch,
+            _ => false,
+        };
+        if send_metadata {
+            if let Some(data) = metadata.metadata_header(options)? {
+                headers.insert(tus::headers::UPLOAD_METADATA.to_owned(), data);
+            }
+        }
+        if let Some(etag) = options.if_match(*self, metadata) {
+            headers.insert(tus::headers::IF_MATCH.to_owned(), etag.to_owned());
         }
         match self {
             TusOp::Create => {
+                // The length of a final upload is the sum of its parts
+                if metadata.defer_length {
+                    headers.insert(
+                        tus::headers::UPLOAD_DEFER_LENGTH.to_owned(),
+                        "1".to_string(),
+                    );
+                } else if !matches!(metadata.concat, Some(UploadConcat::Final(_))) {
+                    headers.insert(
+                        tus::headers::UPLOAD_LENGTH.to_owned(),
+                        format!("{}", metadata.status.size),
+                    );
+                }
+                if let Some(concat) = &metadata.concat {
+                    headers.insert(tus::headers::UPLOAD_CONCAT.to_owned(), concat.to_string());
+                }
+                if let Some(key) = &metadata.idempotency_key {
+                    headers.insert(tus::headers::IDEMPOTENCY_KEY.to_owned(), key.clone());
+                }
+                // Creation With Upload sends the start of the file with the request
+                if let Some(body) = body {
+                    headers.insert(
+                        tus::headers::CONTENT_TYPE.to_owned(),
+                        "application/offset+octet-stream".to_string(),
+                    );
+                    headers.insert(
+                        tus::headers::CONTENT_LENGTH.to_owned(),
+                        body.len().to_string(),
+                    );
+                }
+            }
+            TusOp::Upload => {
                 headers.insert(
-                    tus::headers::UPLOAD_LENGTH.to_owned(),
-                    format!("{}", metadata.status.size),
+                    tus::headers::CONTENT_TYPE.to_owned(),
+                    "application/offset+octet-stream".to_string(),
+                );
+                headers.insert(
+                    tus::headers::UPLOAD_OFFSET.to_owned(),
+                    format!("{}", metadata.status.bytes_uploaded),
+                );
+                let chunk_len = body.map_or(0, <[u8]>::len);
+                headers.insert(
+                    tus::headers::CONTENT_LENGTH.to_owned(),
+                    chunk_len.to_string(),
                 );
+                let sent = metadata.status.bytes_uploaded + chunk_len;
+                let final_chunk = sent == metadata.status.size;
+                if (options.length_on_patch && !metadata.defer_length)
+                    || (options.upload_length_on_final_chunk && final_chunk)
+                {
+                    headers.insert(
+                        tus::headers::UPLOAD_LENGTH.to_owned(),
+                        format!("{}", metadata.status.size),
+                    );
+                }
             }
-            TusOp::Upload => {
+            TusOp::DeclareLength => {
                 headers.insert(
                     tus::headers::CONTENT_TYPE.to_owned(),
                     "application/offset+octet-stream".to_string(),
@@ -78,6 +157,10 @@ impl TusOp {
                     tus::headers::UPLOAD_OFFSET.to_owned(),
                     format!("{}", metadata.status.bytes_uploaded),
                 );
+                headers.insert(
+                    tus::headers::UPLOAD_LENGTH.to_owned(),
+                    format!("{}", metadata.status.size),
+                );
             }
             _ => {}
         }
@@ -86,42 +169,105 @@ impl TusOp {
 
     pub fn url_for_meta(&self, metadata: &UploadMeta) -> Url {
         match self {
-            TusOp::Upload => metadata
+            TusOp::Create => metadata.creation_url(),
+            _ => metadata
                 .remote_url
                 .clone()
-                .unwrap_or(metadata.upload_host.clone())
-                .clone(),
-            _ => metadata.upload_host.clone(),
+                .unwrap_or(metadata.upload_host.clone()),
         }
     }
 
+    /// Update the `metadata` from a successful response
+    ///
+    /// `url` is the url which finally handled the request, after any redirects
     pub fn handle_response(
         &self,
-        response: Response,
+        url: &Url,
+        response_headers: &HeaderMap,
         metadata: &UploadMeta,
+        options: &ClientOptions,
     ) -> Result<UploadMeta, TusError> {
-        let headers: TusHeaders = response.headers().clone().into();
-        match self {
+        // Keep sending to the upload url the server redirected to
+        let metadata = &match self {
+            TusOp::Create => metadata.clone(),
+            _ if url != &self.url_for_meta(metadata) => {
+                metadata.with_remote_dest(url.to_string())?
+            }
+            _ => metadata.clone(),
+        };
+        let headers: TusHeaders = response_headers.clone().into();
+        let updated = match self {
             TusOp::Create => {
-                let remote_dest = headers.location.ok_or(TusError::MissingHeader(
-                    tus::headers::TUS_LOCATION.to_owned(),
-                ))?;
-                metadata.with_remote_dest(remote_dest)
+                // Check the configured header first, then the known names
+                let names = options.location_header.as_deref().into_iter();
+                let remote_dest = names
+                    .chain(tus::headers::LOCATION_HEADERS.iter().copied())
+                    .find_map(|name| response_headers.get(name)?.to_str().ok())
+                    .ok_or(TusError::MissingHeader(
+                        options
+                            .location_header
+                            .clone()
+                            .unwrap_or(tus::headers::TUS_LOCATION.to_owned()),
+                    ))?;
+                // How much of a body sent with Creation With Upload was stored
+                let offset = headers.offset;
+                metadata
+                    .with_remote_dest(remote_dest.to_string())
+                    .and_then(|created| options.check_location(created))
+                    .map(|created| match offset {
+                        Some(offset) => created.with_bytes_uploaded(offset),
+                        None => created,
+                    })
             }
             TusOp::GetOffset => {
-                let offset = headers
-                    .offset
-                    .ok_or(TusError::RequestError("Missing offset".to_string()))?;
-                Ok(metadata.with_bytes_uploaded(offset))
+                let offset = headers.offset.ok_or(TusError::MissingHeader(
+                    tus::headers::UPLOAD_OFFSET.to_owned(),
+                ))?;
+                let updated = match headers.upload_length {
+                    // The length may have been declared since, e.g. by another client
+                    Some(length) => {
+                        metadata.check_server_length(length)?;
+                        metadata.with_server_status(UploadStatus::new(length, Some(offset)))
+                    }
+                    None => metadata.with_bytes_uploaded(offset),
+                };
+                Ok(match headers.upload_metadata {
+                    Some(server) => UploadMeta {
+                        extra_meta: options
+                            .restore_metadata
+                            .restore(updated.extra_meta.clone(), server),
+                        ..updated
+                    },
+                    None => updated,
+                })
             }
             TusOp::Upload => {
-                let offset = headers
-                    .offset
-                    .ok_or(TusError::RequestError("Missing offset".to_string()))?;
+                let offset = headers.offset.ok_or(TusError::MissingHeader(
+                    tus::headers::UPLOAD_OFFSET.to_owned(),
+                ))?;
                 Ok(metadata.with_bytes_uploaded(offset))
             }
+            TusOp::DeclareLength => {
+                let offset = headers.offset.ok_or(TusError::MissingHeader(
+                    tus::headers::UPLOAD_OFFSET.to_owned(),
+                ))?;
+                Ok(UploadMeta {
+                    defer_length: false,
+                    ..metadata.with_bytes_uploaded(offset)
+                })
+            }
             TusOp::Terminate => Ok(metadata.clone()),
-        }
+        }?;
+        // A response without an ETag leaves none to compare the next PATCH against
+        let etag = match options.conditional_requests {
+            true => headers.etag,
+            false => updated.etag.clone(),
+        };
+        Ok(UploadMeta {
+            last_response_headers: options.kept_response_headers(response_headers),
+            etag,
+            ..updated
+        })
     }
 }
 
@@ -129,6 +275,6 @@ impl FromStr for TusOp {
     type Err = TusError;
 
     fn from_str(value: &str) -> Result<Self, Self::Err> {
-        serde_json::from_str(&value).map_err(|_| TusError::SerdeError)
+        serde_json::from_str(value).map_err(|_| TusError::SerdeError)
     }
 }
@@ -0,0 +1,59 @@
+use crate::error::TusError;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+use url::Url;
+
+use super::{upload_meta::UploadMeta, TusVersion};
+
+/// Compact handle on an upload created on the server, to upload it from another service
+///
+/// Formatted as base64 encoded JSON, e.g. to pass between services with `to_string` and
+/// `parse`, then resume with `Client::resume_from_token`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UploadToken {
+    /// url of the upload on the server
+    pub url: Url,
+
+    /// url the upload was created at, to create it again if it expires
+    pub creation_url: Url,
+
+    /// length of the upload in bytes
+    pub size: usize,
+
+    /// TUS version the upload was created with
+    pub version: TusVersion,
+}
+
+impl UploadToken {
+    /// The token for the upload of `meta`
+    ///
+    /// Returns `TusError::MissingUploadUrl` if the upload wasn't created yet
+    pub fn for_meta(meta: &UploadMeta) -> Result<Self, TusError> {
+        Ok(Self {
+            url: meta.require_url()?.clone(),
+            creation_url: meta.creation_url(),
+            size: meta.status.size,
+            version: meta.version.clone(),
+        })
+    }
+}
+
+impl fmt::Display for UploadToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let json = serde_json::to_vec(self).map_err(|_| fmt::Error)?;
+        let token = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json);
+        write!(f, "{token}")
+    }
+}
+
+impl FromStr for UploadToken {
+    type Err = TusError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let json = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(s.trim())
+            .map_err(|_| TusError::StringParseError(format!("Invalid UploadToken String: {s}")))?;
+        serde_json::from_slice(&json).map_err(|_| TusError::SerdeError)
+    }
+}
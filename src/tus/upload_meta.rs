@@ -1,12 +1,19 @@
-use crate::error::TusError;
+use crate::{client::ClientOptions, error::TusError};
 use base64::Engine;
 use serde;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 use url::Url;
 
-use super::UploadStatus;
+use super::{
+    checksum::ChecksumAlgorithm, metadata::UploadMetadata, token::UploadToken, TusVersion,
+    UploadConcat, UploadStats, UploadStatus, TUS_PROTOCOL_VERSION,
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UploadMeta {
@@ -26,14 +33,23 @@ pub struct UploadMeta {
     /// Status of the upload
     pub status: UploadStatus,
 
-    /// TUS version associated with file
-    pub version: String,
+    /// TUS version associated with file, 1.0.0 when missing from a saved meta
+    #[serde(default, deserialize_with = "deserialize_version")]
+    pub version: TusVersion,
 
     /// any extra meta data to include in the upload
     ///  Will be added as base64 key:value encoded pairs
     ///  the UPLOAD_METADATA header value
     pub extra_meta: Option<HashMap<String, String>>,
 
+    /// extra binary meta data, base64 encoded verbatim rather than as UTF-8 text
+    #[serde(default)]
+    pub extra_meta_binary: Option<HashMap<String, Vec<u8>>>,
+
+    /// name sent as the `filename` metadata instead of the base name of `file_path`
+    #[serde(default)]
+    pub display_filename: Option<String>,
+
     /// File type
     pub mime_type: Option<String>,
 
@@ -42,6 +58,77 @@ pub struct UploadMeta {
 
     /// number of times upload attempted/failed
     pub error_count: usize,
+
+    /// byte of the local file the upload starts at, when uploading part of a file
+    #[serde(default)]
+    pub file_offset: usize,
+
+    /// role of the upload in the Concatenation extension
+    pub concat: Option<UploadConcat>,
+
+    /// upload was created without a length, which must be declared later
+    #[serde(default)]
+    pub defer_length: bool,
+
+    /// modification time of the file when the meta was created
+    #[serde(default)]
+    pub file_modified: Option<SystemTime>,
+
+    /// size of the whole local file when the meta was created
+    #[serde(default)]
+    pub file_size: Option<usize>,
+
+    /// sent as the `Idempotency-Key` header when creating the upload
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+
+    /// path joined to `upload_host` the creation request is sent to, for gateways whose
+    /// creation endpoint isn't the upload host itself
+    #[serde(default)]
+    pub creation_path: Option<String>,
+
+    /// headers of the latest response, when `ClientOptions::keep_response_headers` is set
+    #[serde(default)]
+    pub last_response_headers: Option<HashMap<String, String>>,
+
+    /// `ETag` of the latest response, sent as `If-Match` on PATCH requests when
+    /// `ClientOptions::conditional_requests` is set
+    #[serde(default)]
+    pub etag: Option<String>,
+
+    /// metadata computed from the file for `ClientOptions::auto_metadata` when the upload was
+    /// created, sent again on PATCH requests rather than computing it for each
+    #[serde(default)]
+    pub auto_meta: Option<HashMap<String, String>>,
+
+    /// stats of the last `upload`/`resume` session, not persisted
+    #[serde(skip)]
+    pub stats: UploadStats,
+}
+
+/// Deserialize the saved version of a meta, reading the "1" saved by earlier releases, or an
+/// empty version, as 1.0.0
+fn deserialize_version<'de, D: Deserializer<'de>>(deserializer: D) -> Result<TusVersion, D::Error> {
+    let version = String::deserialize(deserializer)?;
+    Ok(match version.trim() {
+        "" | "1" => TusVersion::V1_0_0,
+        version => version.into(),
+    })
+}
+
+/// Checks the upload host is an http(s) url with a host, which reqwest can send to
+fn validate_host(upload_host: &Url) -> Result<(), TusError> {
+    if !matches!(upload_host.scheme(), "http" | "https") {
+        return Err(TusError::InvalidUrl(format!(
+            "{upload_host} - scheme must be http or https"
+        )));
+    }
+    if upload_host.host_str().is_none_or(str::is_empty) {
+        return Err(TusError::InvalidUrl(format!(
+            "{upload_host} - missing host"
+        )));
+    }
+    Ok(())
 }
 
 /// Validates the filename of `file_path` and checks to make sure it is well-formatted
@@ -50,7 +137,7 @@ pub struct UploadMeta {
 /// - file exists
 /// - filename != ""
 /// - filename != "/"
-fn validate_path(file_path: &PathBuf) -> Result<(), TusError> {
+fn validate_path(file_path: &Path) -> Result<(), TusError> {
     if !file_path.exists() {
         return Err(TusError::FileReadError("File not found".to_string()));
     }
@@ -64,7 +151,7 @@ fn validate_path(file_path: &PathBuf) -> Result<(), TusError> {
             "Unable to convert to string".to_string(),
         ))?
         .to_string();
-    if filename == "/".to_string() {
+    if filename == "/" {
         return Err(TusError::InvalidFilename(
             "Filename cannot be '/'".to_string(),
         ));
@@ -80,6 +167,7 @@ impl UploadMeta {
         extra_meta: Option<HashMap<String, String>>,
         custom_headers: Option<HashMap<String, String>>,
     ) -> Result<Self, TusError> {
+        validate_host(&upload_host)?;
         validate_path(&file_path)?;
         let file_meta = file_path.metadata()?;
         let size: usize = file_meta.len() as usize;
@@ -88,11 +176,24 @@ impl UploadMeta {
             file_path,
             upload_host,
             extra_meta,
+            extra_meta_binary: None,
             custom_headers,
             status,
             error_count: 0,
-            version: "1".to_string(), // Version of TUS protocol
+            version: TusVersion::from(TUS_PROTOCOL_VERSION),
             remote_url: None,
+            file_offset: 0,
+            concat: None,
+            defer_length: false,
+            display_filename: None,
+            file_modified: file_meta.modified().ok(),
+            file_size: Some(size),
+            idempotency_key: None,
+            creation_path: None,
+            last_response_headers: None,
+            etag: None,
+            auto_meta: None,
+            stats: UploadStats::default(),
             // with value present
             mime_type: None, // TODO: Set this based on file extension?
         };
@@ -100,20 +201,167 @@ impl UploadMeta {
         Ok(meta)
     }
 
-    // /// Convenience getter to get the filename of the filepath as a string
-    // pub fn filename(&self) -> String {
-    //     self.file_path
-    //         .file_name()
-    //         .ok_or(TusError::EmptyFilename)
-    //         .unwrap()
-    //         .to_str()
-    //         .unwrap()
-    //         .to_string()
-    // }
+    /// Rebuild the metadata for an upload already created on the server at `remote_url`
+    ///
+    /// `bytes_uploaded` starts at 0, use `Client::get_offset` to fetch it from the server
+    /// before resuming
+    pub fn resuming(
+        file_path: PathBuf,
+        upload_host: Url,
+        remote_url: Url,
+    ) -> Result<Self, TusError> {
+        let meta = UploadMeta::new(file_path, upload_host, None, None, None)?;
+        Ok(UploadMeta {
+            remote_url: Some(remote_url),
+            ..meta
+        })
+    }
+
+    /// Name of the file sent as the `filename` metadata
+    ///
+    /// `display_filename` when set, otherwise the base name of `file_path`
+    pub fn filename(&self) -> String {
+        match &self.display_filename {
+            Some(name) => name.clone(),
+            None => self
+                .file_path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The url of the upload on the server, if it has been created
+    pub fn upload_url(&self) -> Option<&Url> {
+        self.remote_url.as_ref()
+    }
+
+    /// The url of the upload on the server
+    ///
+    /// Returns `TusError::MissingUploadUrl` if the upload hasn't been created yet
+    pub fn require_url(&self) -> Result<&Url, TusError> {
+        self.upload_url().ok_or(TusError::MissingUploadUrl)
+    }
+
+    /// The url the creation request is sent to, `creation_path` joined to `upload_host`
+    pub fn creation_url(&self) -> Url {
+        self.creation_path
+            .as_deref()
+            .and_then(|path| self.upload_host.join(path).ok())
+            .unwrap_or_else(|| self.upload_host.clone())
+    }
+
+    /// Position in the local file of the next byte to upload
+    pub fn file_position(&self) -> usize {
+        self.file_offset + self.status.bytes_uploaded
+    }
+
+    /// Number of bytes left to upload
+    pub fn remaining(&self) -> usize {
+        self.status.size.saturating_sub(self.status.bytes_uploaded)
+    }
+
+    /// Check the local file still matches what was recorded when the meta was created
+    ///
+    /// The file must still be `file_size` bytes long, and cover the bytes of the upload. A
+    /// deferred length upload's file may have grown. With `check_modified` the modification
+    /// time must also be unchanged
+    ///
+    /// Returns `TusError::FileChanged` otherwise, or `TusError::FileGone` if the file no
+    /// longer exists
+    pub fn verify_file(&self, check_modified: bool) -> Result<(), TusError> {
+        let file_meta = self
+            .file_path
+            .metadata()
+            .map_err(|e| TusError::from_file_error(&self.file_path, e))?;
+        let len = file_meta.len() as usize;
+        let end = self.file_offset + self.status.size;
+        let expected = match self.file_size {
+            Some(file_size) if !self.defer_length => file_size,
+            _ => end,
+        };
+        if len < end || (len != expected && !self.defer_length) {
+            return Err(TusError::FileChanged(format!(
+                "size is {len} bytes, expected {expected}"
+            )));
+        }
+        if check_modified {
+            if let (Some(recorded), Ok(modified)) = (self.file_modified, file_meta.modified()) {
+                if recorded != modified {
+                    return Err(TusError::FileChanged(
+                        "modification time changed".to_string(),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Save the meta as JSON to `path`
+    ///
+    /// Writes to a temporary file which is renamed over `path`, so an interrupted save never
+    /// leaves a truncated file behind
+    pub fn save(&self, path: &Path) -> Result<(), TusError> {
+        let json = serde_json::to_vec(self).map_err(|_| TusError::SerdeError)?;
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        std::fs::write(&temp_path, json)?;
+        std::fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    /// Load a meta saved with `UploadMeta::save`
+    pub fn load(path: &Path) -> Result<Self, TusError> {
+        let json = std::fs::read(path)?;
+        serde_json::from_slice(&json).map_err(|_| TusError::SerdeError)
+    }
+
+    /// Load every meta saved as a `.json` file in `dir`, e.g. to resume all pending uploads on
+    /// startup, in the order of the file names
+    ///
+    /// Other files are skipped. A file which can't be loaded is reported as
+    /// `TusError::FileReadError` naming it, without stopping the others
+    pub fn load_all(dir: &Path) -> Result<Vec<Result<Self, TusError>>, TusError> {
+        let mut paths = vec![];
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "json") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        Ok(paths
+            .iter()
+            .map(|path| {
+                Self::load(path)
+                    .map_err(|e| TusError::FileReadError(format!("{}: {e}", path.display())))
+            })
+            .collect())
+    }
+
+    /// Restrict the upload to the bytes `range` of the file
+    ///
+    /// Returns `TusError::InvalidRange` if the range is empty or extends past the end of the
+    /// file
+    pub fn with_range(&self, range: Range<usize>) -> Result<Self, TusError> {
+        let file_size = self.file_size.unwrap_or(self.status.size);
+        if range.start >= range.end || range.end > file_size {
+            return Err(TusError::InvalidRange(format!(
+                "{range:?} is not within the {file_size} bytes of the file"
+            )));
+        }
+        Ok(UploadMeta {
+            file_offset: range.start,
+            status: UploadStatus::new(range.len(), None),
+            ..self.clone()
+        })
+    }
 
     /// Check to see if `status.bytes_uploaded` >= `status.size`
+    ///
+    /// Never while `status.length_deferred`, as the size isn't known yet
     pub fn upload_complete(&self) -> bool {
-        self.status.bytes_uploaded >= self.status.size
+        !self.status.length_deferred && self.status.bytes_uploaded >= self.status.size
     }
 
     /// Builds and returns the values to be added to the UPLOAD_METADATA value
@@ -122,10 +370,7 @@ impl UploadMeta {
     /// Calculates filesize and sets mimetype if present
     pub fn data(&self) -> Result<HashMap<String, String>, TusError> {
         let mut h = HashMap::new();
-        h.insert(
-            "filename".to_string(),
-            self.file_path.to_str().unwrap().to_string(),
-        );
+        h.insert("filename".to_string(), self.filename());
         if let Some(mime) = &self.mime_type {
             h.insert("filetype".to_string(), mime.clone());
         }
@@ -139,23 +384,121 @@ impl UploadMeta {
     /// for this upload.
     ///
     /// - converts the key:value pairs to base64 encoding
-    /// - returns all values as a string "key:value,key:value,..."
+    /// - returns all values as a string "key value,key value,..."
+    /// - keys with an empty value are sent bare, without the space and value
+    /// - `extra_meta_binary` values are encoded verbatim
     ///
     /// Calculates filesize and sets mimetype if present
     pub fn data64(&self) -> Result<String, TusError> {
-        let d = self
-            .data()?
-            .into_iter()
-            .map(|(k, v)| {
-                format!(
-                    "{} {}",
-                    k,
-                    base64::engine::general_purpose::STANDARD.encode(v)
-                )
-            })
-            .collect::<Vec<String>>()
-            .join(",");
-        Ok(d)
+        Ok(self.upload_metadata()?.encode())
+    }
+
+    fn upload_metadata(&self) -> Result<UploadMetadata, TusError> {
+        Ok(self
+            .extra_meta_binary
+            .iter()
+            .flatten()
+            .fold(UploadMetadata::from(self.data()?), |metadata, (k, v)| {
+                metadata.set_bytes(k.clone(), v.clone())
+            }))
+    }
+
+    /// The exact `Upload-Metadata` value sent when creating this upload, None when
+    /// `options.send_metadata` is unset
+    ///
+    /// Includes the `options.auto_metadata`, taken from `auto_meta` once the upload was created
+    /// and otherwise computed from the file, so a checksum is computed for every call. Returns
+    /// `TusError::InvalidMetadataKey` for keys which can't be sent
+    pub fn metadata_header(&self, options: &ClientOptions) -> Result<Option<String>, TusError> {
+        if !options.send_metadata {
+            return Ok(None);
+        }
+        let mut metadata = self.upload_metadata()?;
+        let auto_meta = match &self.auto_meta {
+            Some(auto_meta) => auto_meta.clone(),
+            None => self.compute_auto_meta(options)?,
+        };
+        for (key, value) in auto_meta {
+            if metadata.get_bytes(&key).is_none() {
+                metadata = metadata.set(key, value);
+            }
+        }
+        metadata.validate()?;
+        Ok(Some(metadata.encode()))
+    }
+
+    /// The `options.auto_metadata` entries computed from the file
+    fn compute_auto_meta(
+        &self,
+        options: &ClientOptions,
+    ) -> Result<HashMap<String, String>, TusError> {
+        let mut auto_meta = HashMap::new();
+        for auto in &options.auto_metadata {
+            auto_meta.extend(auto.entry(self)?);
+        }
+        Ok(auto_meta)
+    }
+
+    /// Meta with `auto_meta` computed for `options`, to create the upload with, so the
+    /// requests after don't read the file again for it
+    pub(crate) fn with_auto_meta(&self, options: &ClientOptions) -> Result<Self, TusError> {
+        Ok(UploadMeta {
+            auto_meta: Some(self.compute_auto_meta(options)?),
+            ..self.clone()
+        })
+    }
+
+    /// Compute the checksum of the bytes of the file covered by this upload, as lowercase hex
+    ///
+    /// Streams the file rather than reading it into memory. This is an end-to-end checksum of
+    /// the whole upload, unrelated to the per-chunk `Upload-Checksum` of the Checksum extension
+    pub fn compute_checksum(&self, algorithm: ChecksumAlgorithm) -> Result<String, TusError> {
+        let mut file = File::open(&self.file_path)?;
+        file.seek(SeekFrom::Start(self.file_offset as u64))?;
+        let reader = BufReader::new(file).take(self.status.size as u64);
+        algorithm.hex_digest(reader)
+    }
+
+    /// Whether the file matches an `Upload-Checksum` value "<algorithm> <base64 digest>" for
+    /// the whole upload
+    pub fn matches_checksum(&self, upload_checksum: &str) -> Result<bool, TusError> {
+        let (algorithm, digest) =
+            upload_checksum
+                .trim()
+                .split_once(' ')
+                .ok_or(TusError::StringParseError(format!(
+                    "Invalid checksum: {upload_checksum}"
+                )))?;
+        let algorithm: ChecksumAlgorithm = algorithm.parse()?;
+        let expected = base64::engine::general_purpose::STANDARD
+            .decode(digest.trim())
+            .map_err(|e| TusError::StringParseError(format!("Invalid checksum digest: {e}")))?;
+        let expected: String = expected.iter().map(|b| format!("{b:02x}")).collect();
+        Ok(self.compute_checksum(algorithm)? == expected)
+    }
+
+    /// Convenience method to compute the checksum of the file and add it to `extra_meta`
+    ///
+    /// Sent as the `checksum` metadata value "<algorithm> <hex digest>"
+    pub fn with_checksum(&self, algorithm: ChecksumAlgorithm) -> Result<Self, TusError> {
+        let checksum = self.compute_checksum(algorithm)?;
+        let mut extra_meta = self.extra_meta.clone().unwrap_or_default();
+        extra_meta.insert("checksum".to_string(), format!("{algorithm} {checksum}"));
+        Ok(UploadMeta {
+            extra_meta: Some(extra_meta),
+            ..self.clone()
+        })
+    }
+
+    /// Convenience method to change several fields with a single clone, e.g.
+    /// `meta.update(|m| { m.status.bytes_uploaded = 64; m.error_count = 0; })`
+    pub fn update<F>(&self, change: F) -> Self
+    where
+        F: FnOnce(&mut UploadMeta),
+    {
+        let mut meta = self.clone();
+        change(&mut meta);
+        meta
     }
 
     /// Convenience method to create a new meta data struct with updated `status` value
@@ -169,6 +512,133 @@ impl UploadMeta {
         }
     }
 
+    /// Convenience method to take the `status` reported by the server, keeping the local size
+    /// while the server's length is deferred
+    pub fn with_server_status(&self, status: UploadStatus) -> Self {
+        let status = match status.length_deferred {
+            true => UploadStatus {
+                bytes_uploaded: status.bytes_uploaded,
+                ..self.status.clone()
+            },
+            false => status,
+        };
+        UploadMeta {
+            defer_length: self.defer_length && status.length_deferred,
+            status,
+            ..self.clone()
+        }
+    }
+
+    /// A copy to create a new resource for and upload from the start, after the upload expired
+    pub(crate) fn restarted(&self) -> Self {
+        UploadMeta {
+            remote_url: None,
+            status: UploadStatus::new(self.status.size, None),
+            // The key could return the expired upload again
+            idempotency_key: None,
+            ..self.clone()
+        }
+    }
+
+    /// Check the `Upload-Length` the server reports matches the local size, e.g. a server
+    /// reporting 0 for a non-empty file is misconfigured
+    ///
+    /// Always passes while the length is deferred. Returns `TusError::UnequalSizeError`
+    /// otherwise
+    pub fn check_server_length(&self, length: usize) -> Result<(), TusError> {
+        match self.defer_length || length == self.status.size {
+            true => Ok(()),
+            false => Err(TusError::UnequalSizeError {
+                local: self.status.size,
+                server: length,
+            }),
+        }
+    }
+
+    /// Compact token for the upload, to resume it from another service with
+    /// `Client::resume_from_token`
+    ///
+    /// Returns `TusError::MissingUploadUrl` if the upload wasn't created yet
+    pub fn token(&self) -> Result<UploadToken, TusError> {
+        UploadToken::for_meta(self)
+    }
+
+    /// Rebuild the metadata for the upload of `token` from `file_path`
+    ///
+    /// Returns `TusError::UnequalSizeError` if the file isn't the size of the upload.
+    /// `bytes_uploaded` starts at 0, as with `UploadMeta::resuming`
+    pub fn from_token(file_path: PathBuf, token: &UploadToken) -> Result<Self, TusError> {
+        let meta = UploadMeta::resuming(file_path, token.creation_url.clone(), token.url.clone())?
+            .with_version(token.version.clone());
+        match meta.status.size == token.size {
+            true => Ok(meta),
+            false => Err(TusError::UnequalSizeError {
+                local: meta.status.size,
+                server: token.size,
+            }),
+        }
+    }
+
+    /// Convenience method to present the file to the server as `filename`
+    pub fn with_filename(&self, filename: String) -> Self {
+        UploadMeta {
+            display_filename: Some(filename),
+            ..self.clone()
+        }
+    }
+
+    /// Derive an idempotency key from the upload host, file path, size and modification time,
+    /// so creating an upload for the same unchanged file gives the same key
+    pub fn derive_idempotency_key(&self) -> Result<String, TusError> {
+        let path = self
+            .file_path
+            .canonicalize()
+            .unwrap_or_else(|_| self.file_path.clone());
+        let modified = self
+            .file_modified
+            .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_nanos());
+        let material = format!(
+            "{}\n{}\n{}\n{}\n{}",
+            self.upload_host,
+            path.display(),
+            self.file_offset,
+            self.status.size,
+            modified
+        );
+        ChecksumAlgorithm::Sha256.hex_digest(material.as_bytes())
+    }
+
+    /// Convenience method to send the creation request to `path` joined to `upload_host`,
+    /// e.g. "/api/uploads/" or "create/" relative to the host
+    ///
+    /// Returns `TusError::InvalidUrl` if the path can't be joined to the host
+    pub fn with_creation_path(&self, path: &str) -> Result<Self, TusError> {
+        self.upload_host
+            .join(path)
+            .map_err(|e| TusError::InvalidUrl(format!("{path} - {e}")))?;
+        Ok(UploadMeta {
+            creation_path: Some(path.to_string()),
+            ..self.clone()
+        })
+    }
+
+    /// Convenience method to set the idempotency key sent when creating the upload
+    pub fn with_idempotency_key(&self, key: String) -> Self {
+        UploadMeta {
+            idempotency_key: Some(key),
+            ..self.clone()
+        }
+    }
+
+    /// Convenience method to create a new meta data struct with updated `version` value
+    pub fn with_version(&self, version: impl Into<TusVersion>) -> Self {
+        UploadMeta {
+            version: version.into(),
+            ..self.clone()
+        }
+    }
+
     /// Convenience method to update remote_dest property
     pub fn with_remote_dest(&self, remote_url: String) -> Result<Self, TusError> {
         let remote_url = Url::parse(&remote_url)
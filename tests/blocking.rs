@@ -0,0 +1,241 @@
+#![cfg(feature = "blocking")]
+
+mod common;
+
+use common::{create_temp_file, MockResponse, MockServer, RecordedRequest};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::runtime::Runtime;
+use tus_rs::{
+    blocking::Client,
+    client::{ClientOptions, ExpiredPolicy},
+    error::TusError,
+    tus::{upload_meta::UploadMeta, UploadStrategy},
+};
+
+/// Start a `MockServer` answering with `handler`, on a runtime kept alive by the caller
+///
+/// The mock server needs a runtime, the client under test doesn't
+fn start<F>(handler: F) -> (Runtime, MockServer)
+where
+    F: Fn(&RecordedRequest) -> MockResponse + Send + Sync + 'static,
+{
+    let runtime = Runtime::new().unwrap();
+    let server = runtime.block_on(MockServer::start(handler));
+    (runtime, server)
+}
+
+#[test]
+fn should_upload_without_async_runtime() {
+    let (_runtime, server) = start(common::tus_handler);
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::new(64));
+    let info = client.get_server_info(&server.url).unwrap();
+    assert!(!info.extensions.is_empty());
+
+    let meta = client.upload(&path, &server.url, None, None).unwrap();
+    assert!(meta.upload_complete());
+    client.terminate(&meta).unwrap();
+
+    let methods: Vec<_> = server.requests().into_iter().map(|r| r.method).collect();
+    assert_eq!(methods, vec!["OPTIONS", "POST", "PATCH", "PATCH", "DELETE"]);
+}
+
+#[test]
+fn should_resend_chunk_from_server_offset_after_connection_reset() {
+    // The first PATCH stores 32 bytes, then the server goes away without responding
+    let stored = Arc::new(Mutex::new(None));
+    let offset = stored.clone();
+    let (_runtime, server) = start(move |req| match req.method.as_str() {
+        "PATCH" if offset.lock().unwrap().is_none() => {
+            *offset.lock().unwrap() = Some(32);
+            MockResponse::close()
+        }
+        "HEAD" => MockResponse::new(200)
+            .header("tus-resumable", "1.0.0")
+            .header(
+                "upload-offset",
+                &offset.lock().unwrap().unwrap().to_string(),
+            ),
+        _ => common::tus_handler(req),
+    });
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = client.upload(&path, &server.url, None, None).unwrap();
+    assert!(meta.upload_complete());
+
+    let methods: Vec<_> = server.requests().into_iter().map(|r| r.method).collect();
+    assert_eq!(methods, vec!["POST", "PATCH", "HEAD", "PATCH"]);
+    let resent = &server.requests_with_method("PATCH")[1];
+    assert_eq!(resent.header("upload-offset"), Some("32"));
+    assert_eq!(resent.body.len(), 96);
+}
+
+#[test]
+fn should_continue_from_server_offset_after_conflict() {
+    // An earlier PATCH stored 32 bytes the client doesn't know about
+    let conflicts = Arc::new(Mutex::new(1));
+    let remaining = conflicts.clone();
+    let (_runtime, server) = start(move |req| match req.method.as_str() {
+        "PATCH" if *remaining.lock().unwrap() > 0 => {
+            *remaining.lock().unwrap() -= 1;
+            MockResponse::new(409).body("offset is 32")
+        }
+        "HEAD" => MockResponse::new(200)
+            .header("tus-resumable", "1.0.0")
+            .header("upload-offset", "32"),
+        _ => common::tus_handler(req),
+    });
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = client.upload(&path, &server.url, None, None).unwrap();
+    assert!(meta.upload_complete());
+
+    let offsets: Vec<_> = server
+        .requests_with_method("PATCH")
+        .iter()
+        .map(|r| r.header("upload-offset").map(str::to_string))
+        .collect();
+    assert_eq!(offsets, vec![Some("0".to_string()), Some("32".to_string())]);
+    assert_eq!(server.requests_with_method("HEAD").len(), 1);
+}
+
+#[test]
+fn should_recover_offset_when_patch_response_lacks_it() {
+    let (_runtime, server) = start(|req| match req.method.as_str() {
+        "PATCH" => MockResponse::new(204),
+        "HEAD" => MockResponse::new(200).header("upload-offset", "128"),
+        _ => common::tus_handler(req),
+    });
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = client.upload(&path, &server.url, None, None).unwrap();
+    assert!(meta.upload_complete());
+    assert_eq!(server.requests_with_method("HEAD").len(), 1);
+}
+
+#[test]
+fn should_fail_when_offset_missing_from_patch_never_advances() {
+    // HEAD keeps reporting the offset from before the chunk
+    let (_runtime, server) = start(|req| match req.method.as_str() {
+        "PATCH" => MockResponse::new(204),
+        _ => common::tus_handler(req),
+    });
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        chunksize: 64,
+        stalled_patch_limit: 1,
+        ..ClientOptions::default()
+    };
+    let result = Client::new(options).upload(&path, &server.url, None, None);
+    assert_eq!(result.unwrap_err(), TusError::StalledUpload(0));
+    assert_eq!(server.requests_with_method("PATCH").len(), 2);
+    assert_eq!(server.requests_with_method("HEAD").len(), 2);
+}
+
+#[test]
+fn should_retry_locked_uploads() {
+    let locked = Arc::new(Mutex::new(2));
+    let remaining = locked.clone();
+    let (_runtime, server) = start(move |req| match req.method.as_str() {
+        "PATCH" if *remaining.lock().unwrap() > 0 => {
+            *remaining.lock().unwrap() -= 1;
+            MockResponse::new(423)
+        }
+        _ => common::tus_handler(req),
+    });
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        locked_retry_delay: Duration::from_millis(1),
+        ..ClientOptions::default()
+    };
+    let meta = Client::new(options)
+        .upload(&path, &server.url, None, None)
+        .unwrap();
+    assert!(meta.upload_complete());
+    assert_eq!(server.requests_with_method("PATCH").len(), 3);
+}
+
+#[test]
+fn should_recreate_expired_upload_when_configured() {
+    let (_runtime, server) = start(|req| match req.path.as_str() {
+        "/files/expired" => MockResponse::new(404),
+        _ => common::tus_handler(req),
+    });
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let expired = UploadMeta::resuming(
+        path,
+        server.url.clone(),
+        server.url.join("expired").unwrap(),
+    )
+    .unwrap()
+    .with_bytes_uploaded(64);
+
+    let result = Client::new(ClientOptions::default()).resume(&expired);
+    assert!(matches!(result, Err(TusError::NotFoundError(_))));
+
+    let options = ClientOptions {
+        on_expired: ExpiredPolicy::Recreate,
+        ..ClientOptions::default()
+    };
+    let meta = Client::new(options).resume(&expired).unwrap();
+    assert!(meta.upload_complete());
+    assert_eq!(meta.remote_url.unwrap().path(), "/files/1");
+    let last = server.requests_with_method("PATCH").pop().unwrap();
+    assert_eq!(last.header("upload-offset"), Some("0"));
+    assert_eq!(last.body.len(), 128);
+}
+
+#[test]
+fn should_fail_when_server_never_advances_offset() {
+    let (_runtime, server) = start(|req| match req.method.as_str() {
+        "PATCH" => MockResponse::new(204)
+            .header("tus-resumable", "1.0.0")
+            .header("upload-offset", "64"),
+        _ => common::tus_handler(req),
+    });
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        chunksize: 64,
+        stalled_patch_limit: 1,
+        ..ClientOptions::default()
+    };
+    let result = Client::new(options).upload(&path, &server.url, None, None);
+    assert_eq!(result.unwrap_err(), TusError::StalledUpload(64));
+    assert_eq!(server.requests_with_method("PATCH").len(), 3);
+}
+
+#[test]
+fn should_send_small_files_with_the_creation_request() {
+    let (_runtime, server) = start(|req| match req.method.as_str() {
+        "OPTIONS" => common::tus_handler(req).header("tus-extension", "creation-with-upload"),
+        "POST" => common::tus_handler(req).header("upload-offset", &req.body.len().to_string()),
+        _ => common::tus_handler(req),
+    });
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        creation_with_upload: true,
+        ..ClientOptions::default()
+    };
+    let meta = Client::new(options)
+        .upload(&path, &server.url, None, None)
+        .unwrap();
+    assert!(meta.upload_complete());
+    assert_eq!(meta.stats.strategy, UploadStrategy::CreationWithUpload);
+
+    let methods: Vec<_> = server.requests().into_iter().map(|r| r.method).collect();
+    assert_eq!(methods, vec!["OPTIONS", "POST"]);
+    let create = &server.requests_with_method("POST")[0];
+    assert_eq!(create.body, std::fs::read(temp_file.path()).unwrap());
+}
@@ -0,0 +1,236 @@
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    io::Write,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+use tempfile::NamedTempFile;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+use url::Url;
+
+/// A request received by the `MockServer`
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(|v| v.as_str())
+    }
+}
+
+/// A canned response returned by the `MockServer`
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+
+    /// close the connection without responding
+    pub close: bool,
+}
+
+impl MockResponse {
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: vec![],
+            body: String::new(),
+            close: false,
+        }
+    }
+
+    /// Close the connection without a response, like a server going away mid-request
+    pub fn close() -> Self {
+        Self {
+            close: true,
+            ..Self::new(0)
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = body.to_string();
+        self
+    }
+}
+
+type Handler = dyn Fn(&RecordedRequest) -> MockResponse + Send + Sync;
+
+/// Minimal HTTP server which records every request and answers using `handler`
+pub struct MockServer {
+    pub url: Url,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+    connections: Arc<AtomicUsize>,
+}
+
+impl MockServer {
+    pub async fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&RecordedRequest) -> MockResponse + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(vec![]));
+        let handler: Arc<Handler> = Arc::new(handler);
+        let recorded = requests.clone();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    break;
+                };
+                accepted.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(serve(stream, handler.clone(), recorded.clone()));
+            }
+        });
+        Self {
+            url: Url::parse(&format!("http://{addr}/files/")).unwrap(),
+            requests,
+            connections,
+        }
+    }
+
+    /// Start a server which behaves like a well-formed tus server
+    pub async fn tus() -> Self {
+        Self::start(tus_handler).await
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Number of connections accepted so far
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    pub fn requests_with_method(&self, method: &str) -> Vec<RecordedRequest> {
+        self.requests()
+            .into_iter()
+            .filter(|r| r.method == method)
+            .collect()
+    }
+}
+
+/// Stateless tus server behaviour: creation returns `/files/1`, PATCH acknowledges every byte
+pub fn tus_handler(req: &RecordedRequest) -> MockResponse {
+    let host = req.header("host").unwrap_or_default();
+    let method = req
+        .header("x-http-method-override")
+        .unwrap_or(req.method.as_str());
+    match method {
+        "OPTIONS" => MockResponse::new(204)
+            .header("tus-resumable", "1.0.0")
+            .header("tus-version", "1.0.0")
+            .header("tus-extension", "creation,termination,concatenation"),
+        "POST" => {
+            // Partial uploads need distinct urls to be concatenated
+            let id = match req.header("upload-concat") {
+                Some("partial") => uuid::Uuid::new_v4().to_string(),
+                Some(_) => "final".to_string(),
+                None => "1".to_string(),
+            };
+            MockResponse::new(201)
+                .header("tus-resumable", "1.0.0")
+                .header("location", &format!("http://{host}/files/{id}"))
+        }
+        "PATCH" => {
+            let offset: usize = req
+                .header("upload-offset")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_default();
+            MockResponse::new(204)
+                .header("tus-resumable", "1.0.0")
+                .header("upload-offset", &(offset + req.body.len()).to_string())
+        }
+        "HEAD" => MockResponse::new(200)
+            .header("tus-resumable", "1.0.0")
+            .header("upload-offset", "0"),
+        "DELETE" => MockResponse::new(204).header("tus-resumable", "1.0.0"),
+        _ => MockResponse::new(405),
+    }
+}
+
+async fn serve(
+    stream: TcpStream,
+    handler: Arc<Handler>,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+) {
+    let mut reader = BufReader::new(stream);
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await.unwrap_or(0) == 0 {
+            return;
+        }
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let path = parts.next().unwrap_or_default().to_string();
+        let mut headers = HashMap::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await.unwrap_or(0) == 0 {
+                return;
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+            }
+        }
+        let length: usize = headers
+            .get("content-length")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_default();
+        let mut body = vec![0; length];
+        if reader.read_exact(&mut body).await.is_err() {
+            return;
+        }
+        let request = RecordedRequest {
+            method,
+            path,
+            headers,
+            body,
+        };
+        let response = handler(&request);
+        requests.lock().unwrap().push(request);
+        if response.close {
+            return;
+        }
+
+        let mut raw = format!("HTTP/1.1 {} Mock\r\n", response.status);
+        for (name, value) in response.headers.iter() {
+            raw.push_str(&format!("{name}: {value}\r\n"));
+        }
+        raw.push_str(&format!("content-length: {}\r\n\r\n", response.body.len()));
+        raw.push_str(&response.body);
+        if reader.get_mut().write_all(raw.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+pub fn create_temp_file(size: usize) -> NamedTempFile {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    let buffer: Vec<u8> = (0..size).map(|_| rand::random::<u8>()).collect();
+    temp_file.write_all(&buffer[..]).unwrap();
+    temp_file
+}
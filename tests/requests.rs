@@ -0,0 +1,2796 @@
+mod common;
+
+use common::{create_temp_file, MockResponse, MockServer};
+use futures_util::StreamExt;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tus_rs::{
+    client::*,
+    error::TusError,
+    source::ChunkSource,
+    tus::{
+        checksum::ChecksumAlgorithm, token::UploadToken, upload_meta::UploadMeta, TusVersion,
+        UploadConcat, UploadStats, UploadStrategy,
+    },
+};
+use url::Url;
+
+#[tokio::test]
+async fn should_send_configured_tus_version() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        tus_version: "0.2.2".to_string(),
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let result = client.upload(&path, &server.url, None, None).await;
+    assert_eq!(result.unwrap().version, "0.2.2");
+
+    let create = &server.requests_with_method("POST")[0];
+    assert_eq!(create.header("tus-resumable"), Some("0.2.2"));
+    let upload = &server.requests_with_method("PATCH")[0];
+    assert_eq!(upload.header("tus-resumable"), Some("0.2.2"));
+}
+
+#[tokio::test]
+async fn should_override_restricted_methods() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        use_method_override: true,
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let meta = client.upload(&path, &server.url, None, None).await.unwrap();
+    client.terminate(&meta).await.unwrap();
+
+    let requests = server.requests();
+    assert!(requests.iter().all(|r| r.method == "POST"));
+    let overrides: Vec<_> = requests
+        .iter()
+        .map(|r| r.header("x-http-method-override"))
+        .collect();
+    assert_eq!(overrides, vec![None, Some("PATCH"), Some("DELETE")]);
+}
+
+#[tokio::test]
+async fn should_return_server_error_details() {
+    let server = MockServer::start(|_| {
+        MockResponse::new(503)
+            .header("x-request-id", "abc123")
+            .body("maintenance")
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let result = client.create(&path, &server.url, None, None).await;
+    match result {
+        Err(TusError::ServerError {
+            status,
+            headers,
+            body,
+        }) => {
+            assert_eq!(status, 503);
+            assert_eq!(headers.get("x-request-id").unwrap(), "abc123");
+            assert_eq!(body, "maintenance");
+        }
+        other => panic!("unexpected result: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn should_check_upload_exists() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+    assert!(client.exists(&meta).await.unwrap());
+
+    let head = &server.requests_with_method("HEAD")[0];
+    assert_eq!(head.path, "/files/1");
+}
+
+#[tokio::test]
+async fn should_not_find_missing_upload() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "HEAD" => MockResponse::new(404),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+    assert!(!client.exists(&meta).await.unwrap());
+}
+
+#[tokio::test]
+async fn should_report_upload_gone_on_410() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "HEAD" => MockResponse::new(410),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+
+    let result = client.get_offset(&meta).await;
+    assert_eq!(result.unwrap_err(), TusError::Gone(String::new()));
+    assert!(!client.exists(&meta).await.unwrap());
+}
+
+#[tokio::test]
+async fn should_only_accept_compression_when_enabled() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+
+    let client = Client::new(ClientOptions::default());
+    client.create(&path, &server.url, None, None).await.unwrap();
+    let options = ClientOptions {
+        accept_compression: true,
+        ..ClientOptions::default()
+    };
+    let client = Client::try_new(options).unwrap();
+    client.create(&path, &server.url, None, None).await.unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests[0].header("accept-encoding"), None);
+    let encoding = requests[1].header("accept-encoding").unwrap();
+    assert!(encoding.contains("gzip"));
+    assert!(encoding.contains("br"));
+}
+
+#[tokio::test]
+async fn should_send_requests_through_proxy() {
+    let proxy = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        proxy: Some(format!("http://user:secret@{}", proxy.url.authority())),
+        ..ClientOptions::default()
+    };
+    let client = Client::try_new(options).unwrap();
+    let host = Url::parse("http://tus.example/files/").unwrap();
+    client.create(&path, &host, None, None).await.unwrap();
+
+    let create = &proxy.requests()[0];
+    assert_eq!(create.path, "http://tus.example/files/");
+    // base64("user:secret")
+    assert_eq!(
+        create.header("proxy-authorization"),
+        Some("Basic dXNlcjpzZWNyZXQ=")
+    );
+}
+
+#[test]
+fn should_reject_invalid_proxy() {
+    let options = ClientOptions {
+        proxy: Some("http://[::1".to_string()),
+        ..ClientOptions::default()
+    };
+    let result = Client::try_new(options);
+    assert!(matches!(result, Err(TusError::InvalidProxy(_))));
+}
+
+#[test]
+fn should_reject_missing_identity() {
+    let options = ClientOptions {
+        identity: Some(ClientIdentity::Pkcs12 {
+            path: "/does/not/exist.p12".into(),
+            password: String::new(),
+        }),
+        ..ClientOptions::default()
+    };
+    let result = Client::try_new(options);
+    assert!(matches!(result, Err(TusError::InvalidIdentity(_))));
+}
+
+#[test]
+fn should_reject_invalid_root_certificate() {
+    let temp_file = create_temp_file(128);
+    let options = ClientOptions {
+        root_certificate: Some(temp_file.path().into()),
+        ..ClientOptions::default()
+    };
+    let result = Client::try_new(options);
+    assert!(matches!(result, Err(TusError::InvalidCertificate(_))));
+}
+
+#[test]
+fn should_default_options_missing_from_config() {
+    let options: ClientOptions = serde_json::from_str(r#"{"chunksize": 1024}"#).unwrap();
+    assert_eq!(options.chunksize, 1024);
+    assert_eq!(options.tus_version, "1.0.0");
+    assert_eq!(options.stalled_patch_limit, 3);
+    assert!(options.send_metadata);
+}
+
+#[tokio::test]
+async fn should_verify_size_after_upload() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "HEAD" => MockResponse::new(200).header("upload-offset", "128"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        verify_size: true,
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let result = client.upload(&path, &server.url, None, None).await;
+    assert!(result.is_ok());
+    assert_eq!(server.requests_with_method("HEAD").len(), 1);
+}
+
+#[tokio::test]
+async fn should_fail_verification_on_unequal_size() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "HEAD" => MockResponse::new(200).header("upload-offset", "200"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        verify_size: true,
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let result = client.upload(&path, &server.url, None, None).await;
+    assert_eq!(
+        result.unwrap_err(),
+        TusError::UnequalSizeError {
+            local: 128,
+            server: 200
+        }
+    );
+}
+
+#[tokio::test]
+async fn should_fail_when_server_reports_zero_length_for_non_empty_file() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "HEAD" => MockResponse::new(200)
+            .header("upload-offset", "0")
+            .header("upload-length", "0"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(100);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+    let expected = TusError::UnequalSizeError {
+        local: 100,
+        server: 0,
+    };
+
+    assert_eq!(client.get_offset(&meta).await.unwrap_err(), expected);
+    assert_eq!(client.sync(&meta).await.unwrap_err(), expected);
+    assert!(server.requests_with_method("PATCH").is_empty());
+}
+
+#[tokio::test]
+async fn should_follow_permanent_redirect_mid_upload() {
+    let server = MockServer::start(|req| match (req.method.as_str(), req.path.as_str()) {
+        ("PATCH", "/files/1") => {
+            let host = req.header("host").unwrap();
+            MockResponse::new(308).header("location", &format!("http://{host}/files/2"))
+        }
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::new(64));
+    let meta = client.upload(&path, &server.url, None, None).await.unwrap();
+    assert_eq!(meta.remote_url.unwrap().path(), "/files/2");
+
+    let patches = server.requests_with_method("PATCH");
+    let paths: Vec<_> = patches.iter().map(|r| r.path.as_str()).collect();
+    assert_eq!(paths, vec!["/files/1", "/files/2", "/files/2"]);
+    assert!(patches.iter().all(|r| r.body.len() == 64));
+}
+
+#[tokio::test]
+async fn should_not_follow_cross_host_redirect() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "PATCH" => MockResponse::new(307).header("location", "http://tus.example/files/1"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let result = client.upload(&path, &server.url, None, None).await;
+    assert!(matches!(result, Err(TusError::RequestError(_))));
+}
+
+#[tokio::test]
+async fn should_not_follow_redirect_to_another_port() {
+    let other = MockServer::tus().await;
+    let location = other.url.join("1").unwrap().to_string();
+    let server = MockServer::start(move |req| match req.method.as_str() {
+        "PATCH" => MockResponse::new(307).header("location", &location),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let result = client.upload(&path, &server.url, None, None).await;
+    assert!(matches!(result, Err(TusError::RequestError(_))));
+    assert!(other.requests().is_empty());
+}
+
+#[tokio::test]
+async fn should_grow_adaptive_chunksize_on_fast_uploads() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(256);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        chunksize: 16,
+        adaptive_chunksize: Some(AdaptiveChunksize::new(16, 64, Duration::from_secs(5))),
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let meta = client.upload(&path, &server.url, None, None).await.unwrap();
+    assert!(meta.upload_complete());
+
+    let sizes: Vec<_> = server
+        .requests_with_method("PATCH")
+        .iter()
+        .map(|r| r.body.len())
+        .collect();
+    assert_eq!(sizes, vec![16, 32, 64, 64, 64, 16]);
+}
+
+#[tokio::test]
+async fn should_record_stats_of_the_upload_session() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(100);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::new(32));
+    let meta = client.upload(&path, &server.url, None, None).await.unwrap();
+    assert_eq!(meta.stats.bytes_sent, 100);
+    assert_eq!(meta.stats.patch_requests, 4);
+
+    // A resume of a complete upload sends nothing
+    let meta = client.resume(&meta).await.unwrap();
+    assert_eq!(meta.stats.bytes_sent, 0);
+    assert_eq!(meta.stats.patch_requests, 0);
+}
+
+#[test]
+fn should_merge_upload_stats() {
+    let first = UploadStats {
+        bytes_sent: 100,
+        patch_requests: 2,
+        elapsed: Duration::from_secs(1),
+        ..UploadStats::default()
+    };
+    let merged = first.merge(&UploadStats {
+        bytes_sent: 300,
+        patch_requests: 1,
+        elapsed: Duration::from_secs(1),
+        ..UploadStats::default()
+    });
+    assert_eq!(merged.bytes_sent, 400);
+    assert_eq!(merged.patch_requests, 3);
+    assert_eq!(merged.throughput(), 200.0);
+    assert_eq!(UploadStats::default().throughput(), 0.0);
+}
+
+#[test]
+fn should_adapt_chunksize_towards_target_duration() {
+    let adaptive = AdaptiveChunksize::new(10, 1000, Duration::from_secs(4));
+    assert_eq!(adaptive.next_chunksize(100, Duration::from_secs(2)), 200);
+    assert_eq!(adaptive.next_chunksize(100, Duration::from_secs(5)), 80);
+    assert_eq!(adaptive.next_chunksize(100, Duration::from_secs(60)), 50);
+    assert_eq!(adaptive.next_chunksize(800, Duration::from_secs(1)), 1000);
+    assert_eq!(adaptive.next_chunksize(15, Duration::from_secs(60)), 10);
+}
+
+#[test]
+fn should_count_chunks_of_upload() {
+    let options = ClientOptions::new(64);
+    assert_eq!(options.chunk_count(0), 1);
+    assert_eq!(options.chunk_count(1), 1);
+    assert_eq!(options.chunk_count(64), 1);
+    assert_eq!(options.chunk_count(65), 2);
+    assert_eq!(options.chunk_count(640), 10);
+
+    let aligned = ClientOptions {
+        chunk_alignment: Some(48),
+        ..ClientOptions::new(64)
+    };
+    assert_eq!(aligned.chunk_count(100), 3);
+}
+
+#[tokio::test]
+async fn should_reject_zero_chunksize() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::new(0));
+    let result = client.upload(&path, &server.url, None, None).await;
+    assert!(matches!(result, Err(TusError::InvalidChunkSize(0))));
+    assert_eq!(
+        format!("{}", result.unwrap_err()),
+        "Invalid chunksize: 0 - must be at least 1 byte"
+    );
+}
+
+#[tokio::test]
+async fn should_upload_parts_in_parallel() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(250);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::new(50));
+    let progress = Arc::new(Mutex::new(vec![]));
+    let reported = progress.clone();
+    let meta = client
+        .upload_parallel(&path, &server.url, 4, None, None, move |status| {
+            reported.lock().unwrap().push(status.bytes_uploaded)
+        })
+        .await
+        .unwrap();
+    assert!(meta.upload_complete());
+    assert_eq!(meta.remote_url.unwrap().path(), "/files/final");
+
+    let creates = server.requests_with_method("POST");
+    let (parts, last) = creates.split_at(4);
+    assert!(parts
+        .iter()
+        .all(|r| r.header("upload-concat") == Some("partial")));
+    let mut lengths: Vec<_> = parts
+        .iter()
+        .map(|r| r.header("upload-length").unwrap())
+        .collect();
+    lengths.sort();
+    assert_eq!(lengths, vec!["61", "63", "63", "63"]);
+    assert_eq!(last[0].header("upload-length"), None);
+
+    // Parts are concatenated in file order, whichever finished first
+    let concat = last[0].header("upload-concat").unwrap();
+    let urls = concat.strip_prefix("final;").unwrap().split(' ');
+    let patches = server.requests_with_method("PATCH");
+    let mut uploaded = vec![];
+    for url in urls {
+        let path = Url::parse(url).unwrap().path().to_string();
+        for patch in patches.iter().filter(|r| r.path == path) {
+            uploaded.extend_from_slice(&patch.body);
+        }
+    }
+    assert_eq!(uploaded, std::fs::read(temp_file.path()).unwrap());
+
+    let progress = progress.lock().unwrap();
+    assert_eq!(progress.len(), patches.len());
+    assert_eq!(*progress.iter().max().unwrap(), 250);
+}
+
+#[tokio::test]
+async fn should_report_parallel_progress_of_recreated_part() {
+    // One part expires after its first chunk and is uploaded again from the start
+    let expired = Arc::new(Mutex::new(false));
+    let server = MockServer::start(move |req| match req.method.as_str() {
+        "PATCH" if req.header("upload-offset") == Some("32") && !*expired.lock().unwrap() => {
+            *expired.lock().unwrap() = true;
+            MockResponse::new(404)
+        }
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions {
+        chunksize: 32,
+        on_expired: ExpiredPolicy::Recreate,
+        ..ClientOptions::default()
+    });
+    let progress = Arc::new(Mutex::new(vec![]));
+    let reported = progress.clone();
+    let meta = client
+        .upload_parallel(&path, &server.url, 2, None, None, move |status| {
+            reported.lock().unwrap().push(status.bytes_uploaded)
+        })
+        .await
+        .unwrap();
+    assert!(meta.upload_complete());
+    assert_eq!(server.requests_with_method("POST").len(), 4);
+
+    let progress = progress.lock().unwrap();
+    assert_eq!(*progress.iter().max().unwrap(), 128);
+    assert_eq!(progress.last(), Some(&128));
+}
+
+#[tokio::test]
+async fn should_upload_sequentially_without_concatenation() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "OPTIONS" => MockResponse::new(204)
+            .header("tus-version", "1.0.0")
+            .header("tus-extension", "creation"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = client
+        .upload_parallel(&path, &server.url, 4, None, None, |_| {})
+        .await
+        .unwrap();
+    assert!(meta.upload_complete());
+    assert_eq!(server.requests_with_method("POST").len(), 1);
+    assert_eq!(server.requests_with_method("PATCH").len(), 1);
+}
+
+#[tokio::test]
+async fn should_declare_deferred_length() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let mut meta = client
+        .create_deferred(&path, &server.url, None, None)
+        .await
+        .unwrap();
+    assert!(meta.defer_length);
+    client.set_length(&mut meta, 128).await.unwrap();
+    assert!(!meta.defer_length);
+    assert_eq!(meta.status.size, 128);
+
+    let create = &server.requests_with_method("POST")[0];
+    assert_eq!(create.header("upload-defer-length"), Some("1"));
+    assert_eq!(create.header("upload-length"), None);
+    let declare = &server.requests_with_method("PATCH")[0];
+    assert_eq!(declare.header("upload-length"), Some("128"));
+    assert!(declare.body.is_empty());
+
+    let result = client.set_length(&mut meta, 128).await;
+    assert!(matches!(result, Err(TusError::LengthNotDeferred)));
+}
+
+#[tokio::test]
+async fn should_fail_when_server_rejects_length() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "PATCH" => MockResponse::new(400).body("length already set"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let mut meta = client
+        .create_deferred(&path, &server.url, None, None)
+        .await
+        .unwrap();
+    let result = client.set_length(&mut meta, 128).await;
+    assert!(matches!(result, Err(TusError::BadRequest(body)) if body == "length already set"));
+}
+
+#[tokio::test]
+async fn should_not_let_custom_headers_override_protocol_headers() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let custom_headers = HashMap::from([
+        ("Upload-Offset".to_string(), "999".to_string()),
+        ("Tus-Resumable".to_string(), "0.0.1".to_string()),
+        ("Authorization".to_string(), "Bearer token".to_string()),
+    ]);
+    client
+        .upload(&path, &server.url, None, Some(custom_headers))
+        .await
+        .unwrap();
+
+    for request in server.requests() {
+        assert_eq!(request.header("tus-resumable"), Some("1.0.0"));
+        assert_eq!(request.header("authorization"), Some("Bearer token"));
+    }
+    let upload = &server.requests_with_method("PATCH")[0];
+    assert_eq!(upload.header("upload-offset"), Some("0"));
+}
+
+#[tokio::test]
+async fn should_send_extra_headers_with_one_call_only() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let custom_headers = HashMap::from([("X-Tenant".to_string(), "default".to_string())]);
+    let routing = HashMap::from([
+        ("x-tenant".to_string(), "eu-1".to_string()),
+        ("Upload-Length".to_string(), "1".to_string()),
+    ]);
+    let meta = client
+        .create_with_headers(&path, &server.url, None, Some(custom_headers), routing)
+        .await
+        .unwrap();
+    let trace = HashMap::from([("X-Trace".to_string(), "resume-1".to_string())]);
+    let meta = client.resume_with_headers(&meta, trace).await.unwrap();
+    client.terminate(&meta).await.unwrap();
+
+    let create = &server.requests_with_method("POST")[0];
+    assert_eq!(create.header("x-tenant"), Some("eu-1"));
+    assert_eq!(create.header("upload-length"), Some("128"));
+    let patch = &server.requests_with_method("PATCH")[0];
+    assert_eq!(patch.header("x-tenant"), Some("default"));
+    assert_eq!(patch.header("x-trace"), Some("resume-1"));
+    let delete = &server.requests_with_method("DELETE")[0];
+    assert_eq!(delete.header("x-tenant"), Some("default"));
+    assert_eq!(delete.header("x-trace"), None);
+}
+
+#[tokio::test]
+async fn should_map_status_codes_with_hook() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "PATCH" => MockResponse::new(498).header("x-reason", "token expired"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions::default().with_status_hook(|status, headers| match status {
+        498 => Some(TusError::RequestError(
+            headers.get("x-reason")?.to_str().ok()?.to_string(),
+        )),
+        _ => None,
+    });
+    let client = Client::new(options);
+    let result = client.upload(&path, &server.url, None, None).await;
+    assert!(matches!(result, Err(TusError::RequestError(reason)) if reason == "token expired"));
+}
+
+#[tokio::test]
+async fn should_keep_server_message_on_offset_conflict() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "PATCH" => MockResponse::new(409).body("offset 0 does not match 64"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let result = client.upload(&path, &server.url, None, None).await;
+    assert!(matches!(
+        result,
+        Err(TusError::WrongUploadOffsetError(body)) if body == "offset 0 does not match 64"
+    ));
+}
+
+#[test]
+fn should_compare_errors_by_payload() {
+    assert_eq!(
+        TusError::WrongUploadOffsetError("expected 64".to_string()),
+        TusError::WrongUploadOffsetError("expected 64".to_string())
+    );
+    assert_ne!(
+        TusError::WrongUploadOffsetError("expected 64".to_string()),
+        TusError::WrongUploadOffsetError("expected 0".to_string())
+    );
+    assert_ne!(TusError::Cancelled, TusError::MissingUploadUrl);
+    // Opaque errors only compare their variant
+    assert_eq!(
+        TusError::from(std::io::Error::other("disk full")),
+        TusError::from(std::io::Error::other("permission denied"))
+    );
+}
+
+#[tokio::test]
+async fn should_fail_when_server_never_advances_offset() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "PATCH" => MockResponse::new(204)
+            .header("tus-resumable", "1.0.0")
+            .header("upload-offset", "64"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    for limit in [0, 2] {
+        let options = ClientOptions {
+            chunksize: 64,
+            stalled_patch_limit: limit,
+            ..ClientOptions::default()
+        };
+        let before = server.requests_with_method("PATCH").len();
+        let result = Client::new(options)
+            .upload(&path, &server.url, None, None)
+            .await;
+        assert_eq!(result.unwrap_err(), TusError::StalledUpload(64));
+        // The first PATCH advanced the offset, `limit` stalls were tolerated
+        let patches = server.requests_with_method("PATCH").len() - before;
+        assert_eq!(patches, limit + 2);
+    }
+}
+
+#[tokio::test]
+async fn should_upload_advancing_chunks_without_stall_tolerance() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        chunksize: 32,
+        stalled_patch_limit: 0,
+        ..ClientOptions::default()
+    };
+    let meta = Client::new(options)
+        .upload(&path, &server.url, None, None)
+        .await
+        .unwrap();
+    assert!(meta.upload_complete());
+    assert_eq!(server.requests_with_method("PATCH").len(), 4);
+}
+
+#[tokio::test]
+async fn should_continue_from_server_offset_after_conflict() {
+    // An earlier PATCH stored 32 bytes the client doesn't know about
+    let conflicts = Arc::new(Mutex::new(1));
+    let remaining = conflicts.clone();
+    let server = MockServer::start(move |req| match req.method.as_str() {
+        "PATCH" if *remaining.lock().unwrap() > 0 => {
+            *remaining.lock().unwrap() -= 1;
+            MockResponse::new(409).body("offset is 32")
+        }
+        "HEAD" => MockResponse::new(200)
+            .header("tus-resumable", "1.0.0")
+            .header("upload-offset", "32"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = client.upload(&path, &server.url, None, None).await.unwrap();
+    assert!(meta.upload_complete());
+
+    let offsets: Vec<_> = server
+        .requests_with_method("PATCH")
+        .iter()
+        .map(|r| r.header("upload-offset").map(str::to_string))
+        .collect();
+    assert_eq!(offsets, vec![Some("0".to_string()), Some("32".to_string())]);
+    assert_eq!(server.requests_with_method("HEAD").len(), 1);
+}
+
+#[tokio::test]
+async fn should_get_offset_as_number() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "HEAD" => MockResponse::new(200).header("upload-offset", "42"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+    assert_eq!(client.offset(&meta).await.unwrap(), 42);
+}
+
+#[tokio::test]
+async fn should_only_send_metadata_on_create_by_default() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    client.upload(&path, &server.url, None, None).await.unwrap();
+
+    let create = &server.requests_with_method("POST")[0];
+    assert!(create.header("upload-metadata").is_some());
+    for request in server.requests_with_method("PATCH") {
+        assert_eq!(request.header("upload-metadata"), None);
+    }
+}
+
+#[tokio::test]
+async fn should_send_metadata_on_patch_when_enabled() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        metadata_on_patch: true,
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    client.upload(&path, &server.url, None, None).await.unwrap();
+
+    let create = &server.requests_with_method("POST")[0];
+    let upload = &server.requests_with_method("PATCH")[0];
+    assert!(upload.header("upload-metadata").is_some());
+    assert_eq!(
+        upload.header("upload-metadata"),
+        create.header("upload-metadata")
+    );
+}
+
+#[tokio::test]
+async fn should_compute_auto_metadata_once_on_creation() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        chunksize: 64,
+        metadata_on_patch: true,
+        auto_metadata: [AutoMeta::Checksum(ChecksumAlgorithm::Sha256)].into(),
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+    assert!(meta.auto_meta.as_ref().unwrap().contains_key("checksum"));
+
+    // Rewriting the file doesn't change the checksum computed when it was created
+    std::fs::write(temp_file.path(), [0; 128]).unwrap();
+    client.resume(&meta).await.unwrap();
+
+    let create = &server.requests_with_method("POST")[0];
+    for upload in server.requests_with_method("PATCH") {
+        assert_eq!(
+            upload.header("upload-metadata"),
+            create.header("upload-metadata")
+        );
+    }
+}
+
+#[tokio::test]
+async fn should_not_send_metadata_when_disabled() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        send_metadata: false,
+        metadata_on_patch: true,
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let metadata = HashMap::from([("key".to_string(), "value".to_string())]);
+    client
+        .upload(&path, &server.url, Some(metadata), None)
+        .await
+        .unwrap();
+
+    assert!(server
+        .requests()
+        .iter()
+        .all(|r| r.header("upload-metadata").is_none()));
+}
+
+#[tokio::test]
+async fn should_report_versions_supported_by_server() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "POST" => MockResponse::new(412).header("tus-version", "0.2.2, 0.2.1"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let result = client.create(&path, &server.url, None, None).await;
+    assert!(matches!(
+        result,
+        Err(TusError::UnsupportedVersion(versions)) if versions == ["0.2.2", "0.2.1"]
+    ));
+}
+
+#[test]
+fn should_build_options_with_default_trait() {
+    fn defaulted<T: Default>() -> T {
+        T::default()
+    }
+    let options = ClientOptions {
+        chunksize: 1024,
+        ..Default::default()
+    };
+    assert_eq!(options.chunksize, 1024);
+    assert_eq!(defaulted::<ClientOptions>().chunksize, 6 * 1024 * 1024);
+    assert_eq!(ClientOptions::from(2048).chunksize, 2048);
+    assert_eq!(ClientOptions::from(2048).tus_version, "1.0.0");
+}
+
+#[tokio::test]
+async fn should_align_chunks_to_configured_multiple() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(1000);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        chunksize: 250,
+        chunk_alignment: Some(64),
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    client.upload(&path, &server.url, None, None).await.unwrap();
+
+    let sizes: Vec<usize> = server
+        .requests_with_method("PATCH")
+        .iter()
+        .map(|request| request.body.len())
+        .collect();
+    assert_eq!(sizes, [192, 192, 192, 192, 192, 40]);
+}
+
+#[tokio::test]
+async fn should_fail_when_file_changed_before_resume() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+    temp_file.as_file().set_len(64).unwrap();
+
+    let result = client.resume(&meta).await;
+    assert!(matches!(result, Err(TusError::FileChanged(_))));
+    assert!(server.requests_with_method("PATCH").is_empty());
+}
+
+#[tokio::test]
+async fn should_report_file_gone_before_resume() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path: std::path::PathBuf = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+    temp_file.close().unwrap();
+
+    let result = client.resume(&meta).await;
+    match result {
+        Err(TusError::FileGone { path: gone, source }) => {
+            assert_eq!(gone, path);
+            assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+        }
+        other => panic!("unexpected result: {other:?}"),
+    }
+    assert!(server.requests_with_method("PATCH").is_empty());
+}
+
+#[tokio::test]
+async fn should_fail_when_modified_time_changed() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        verify_modified: true,
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+    let modified = meta.file_modified.unwrap() + Duration::from_secs(60);
+    temp_file.as_file().set_modified(modified).unwrap();
+
+    let result = client.resume(&meta).await;
+    assert!(matches!(result, Err(TusError::FileChanged(_))));
+}
+
+#[tokio::test]
+async fn should_resume_from_checkpoint_after_interruption() {
+    let patches = Arc::new(Mutex::new(0));
+    let counter = patches.clone();
+    let server = MockServer::start(move |req| match req.method.as_str() {
+        "PATCH" => {
+            let mut count = counter.lock().unwrap();
+            *count += 1;
+            match *count {
+                2 => MockResponse::new(500),
+                _ => common::tus_handler(req),
+            }
+        }
+        "HEAD" => MockResponse::new(200).header("upload-offset", "64"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let checkpoint_dir = tempfile::tempdir().unwrap();
+    let checkpoint_path = checkpoint_dir.path().join("upload.json");
+    let options = ClientOptions {
+        chunksize: 64,
+        checkpoint_path: Some(checkpoint_path.clone()),
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+    assert!(client.resume(&meta).await.is_err());
+    let checkpoint = UploadMeta::load(&checkpoint_path).unwrap();
+    assert_eq!(checkpoint.status.bytes_uploaded, 64);
+
+    // A fresh meta without the remote url picks up the checkpoint
+    let fresh = UploadMeta::new(path.clone(), server.url.clone(), None, None, None).unwrap();
+    let result = client.resume(&fresh).await.unwrap();
+    assert!(result.upload_complete());
+    assert_eq!(result.remote_url, meta.remote_url);
+    let last = server.requests_with_method("PATCH").pop().unwrap();
+    assert_eq!(last.header("upload-offset"), Some("64"));
+    assert!(!checkpoint_path.exists());
+}
+
+#[tokio::test]
+async fn should_get_server_info_from_existing_response() {
+    let server = MockServer::tus().await;
+    let response = reqwest::Client::new()
+        .request(reqwest::Method::OPTIONS, server.url.clone())
+        .send()
+        .await
+        .unwrap();
+    let client = Client::new(ClientOptions::default());
+    let info = client.server_info_from_response(response).await.unwrap();
+    assert_eq!(info.supported_versions, ["1.0.0"]);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn should_mark_short_final_chunk() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(100);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        chunksize: 64,
+        upload_length_on_final_chunk: true,
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let result = client.upload(&path, &server.url, None, None).await.unwrap();
+    assert!(result.upload_complete());
+
+    let patches = server.requests_with_method("PATCH");
+    assert_eq!(patches.len(), 2);
+    assert_eq!(patches[0].header("upload-length"), None);
+    assert_eq!(patches[1].header("upload-length"), Some("100"));
+    for patch in &patches {
+        let length = patch.body.len().to_string();
+        assert_eq!(patch.header("content-length"), Some(length.as_str()));
+    }
+    assert_eq!(patches[1].body.len(), 36);
+}
+
+#[tokio::test]
+async fn should_send_upload_length_on_every_patch_when_enabled() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(100);
+    let path = temp_file.path().into();
+    Client::new(ClientOptions::new(64))
+        .upload(&path, &server.url, None, None)
+        .await
+        .unwrap();
+    let options = ClientOptions {
+        length_on_patch: true,
+        ..ClientOptions::new(64)
+    };
+    Client::new(options)
+        .upload(&path, &server.url, None, None)
+        .await
+        .unwrap();
+
+    let lengths: Vec<_> = server
+        .requests_with_method("PATCH")
+        .iter()
+        .map(|r| r.header("upload-length").map(str::to_string))
+        .collect();
+    let length = Some("100".to_string());
+    assert_eq!(lengths, vec![None, None, length.clone(), length]);
+}
+
+#[tokio::test]
+async fn should_report_missing_offset_header() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "HEAD" => MockResponse::new(200),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+    let result = client.get_offset(&meta).await;
+    assert!(matches!(result, Err(TusError::MissingHeader(header)) if header == "upload-offset"));
+}
+
+#[tokio::test]
+async fn should_recover_offset_when_patch_response_lacks_it() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "PATCH" => MockResponse::new(204),
+        "HEAD" => MockResponse::new(200).header("upload-offset", "128"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let result = client.upload(&path, &server.url, None, None).await.unwrap();
+    assert!(result.upload_complete());
+    assert_eq!(server.requests_with_method("HEAD").len(), 1);
+}
+
+#[tokio::test]
+async fn should_fail_when_offset_missing_from_patch_never_advances() {
+    // HEAD keeps reporting the offset from before the chunk
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "PATCH" => MockResponse::new(204),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        chunksize: 64,
+        stalled_patch_limit: 1,
+        ..ClientOptions::default()
+    };
+    let result = Client::new(options)
+        .upload(&path, &server.url, None, None)
+        .await;
+    assert_eq!(result.unwrap_err(), TusError::StalledUpload(0));
+    assert_eq!(server.requests_with_method("PATCH").len(), 2);
+    assert_eq!(server.requests_with_method("HEAD").len(), 2);
+}
+
+/// Records the length of every chunk requested from it
+struct RecordingSource {
+    data: Vec<u8>,
+    requested: Vec<usize>,
+}
+
+impl ChunkSource for RecordingSource {
+    fn read_chunk(&mut self, offset: usize, max_len: usize) -> Result<Vec<u8>, TusError> {
+        self.requested.push(max_len);
+        self.data.read_chunk(offset, max_len)
+    }
+}
+
+#[tokio::test]
+async fn should_not_read_chunks_beyond_the_file() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(100);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+
+    let mut source = RecordingSource {
+        data: vec![0; 100],
+        requested: vec![],
+    };
+    client.resume_from(&meta, &mut source).await.unwrap();
+    assert_eq!(source.requested, vec![100]);
+}
+
+#[tokio::test]
+async fn should_resume_from_chunk_source() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(100);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::new(40));
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+
+    let mut source: Vec<u8> = (0..100).collect();
+    let result = client.resume_from(&meta, &mut source).await.unwrap();
+    assert!(result.upload_complete());
+    let sent: Vec<u8> = server
+        .requests_with_method("PATCH")
+        .into_iter()
+        .flat_map(|request| request.body)
+        .collect();
+    assert_eq!(sent, source);
+}
+
+/// Checks every chunk sent is a slice of `data` rather than a copy
+struct SharedBytesSource {
+    data: bytes::Bytes,
+    chunks: usize,
+}
+
+impl ChunkSource for SharedBytesSource {
+    fn read_chunk(&mut self, _offset: usize, _max_len: usize) -> Result<Vec<u8>, TusError> {
+        panic!("chunk copied into a Vec")
+    }
+
+    fn read_bytes(&mut self, offset: usize, max_len: usize) -> Result<bytes::Bytes, TusError> {
+        let chunk = self.data.read_bytes(offset, max_len)?;
+        assert_eq!(chunk.as_ptr(), self.data[offset..].as_ptr());
+        self.chunks += 1;
+        Ok(chunk)
+    }
+}
+
+#[tokio::test]
+async fn should_upload_in_memory_bytes_without_copying_chunks() {
+    let server = MockServer::tus().await;
+    let size = 4 * 1024 * 1024;
+    let temp_file = create_temp_file(size);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::new(1024 * 1024));
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+
+    let data: Vec<u8> = (0..size).map(|i| i as u8).collect();
+    let mut source = SharedBytesSource {
+        data: bytes::Bytes::from(data.clone()),
+        chunks: 0,
+    };
+    let result = client.resume_from(&meta, &mut source).await.unwrap();
+    assert!(result.upload_complete());
+    assert_eq!(source.chunks, 4);
+    let sent: Vec<u8> = server
+        .requests_with_method("PATCH")
+        .into_iter()
+        .flat_map(|request| request.body)
+        .collect();
+    assert_eq!(sent, data);
+}
+
+#[tokio::test]
+async fn should_resume_from_token_created_elsewhere() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "HEAD" => MockResponse::new(200)
+            .header("upload-offset", "64")
+            .header("upload-length", "128"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let creator = Client::new(ClientOptions::default());
+    let meta = creator
+        .create(&path, &server.url, None, None)
+        .await
+        .unwrap();
+    let token = meta.token().unwrap().to_string();
+
+    let token: UploadToken = token.parse().unwrap();
+    assert_eq!(token.url, server.url.join("1").unwrap());
+    assert_eq!(token.creation_url, server.url);
+    assert_eq!(token.size, 128);
+    let uploader = Client::new(ClientOptions::default());
+    let meta = uploader.resume_from_token(&token, &path).await.unwrap();
+    assert!(meta.upload_complete());
+    assert_eq!(meta.creation_url(), server.url);
+    let patch = &server.requests_with_method("PATCH")[0];
+    assert_eq!(patch.path, "/files/1");
+    assert_eq!(patch.header("upload-offset"), Some("64"));
+
+    let other = create_temp_file(100);
+    let result = uploader.resume_from_token(&token, other.path()).await;
+    assert_eq!(
+        result.unwrap_err(),
+        TusError::UnequalSizeError {
+            local: 100,
+            server: 128
+        }
+    );
+    assert!("not a token".parse::<UploadToken>().is_err());
+}
+
+#[tokio::test]
+async fn should_recreate_expired_upload_from_token_at_creation_url() {
+    let server = MockServer::start(|req| match req.path.as_str() {
+        "/files/expired" => MockResponse::new(404),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let created = UploadMeta::resuming(
+        path,
+        server.url.clone(),
+        server.url.join("expired").unwrap(),
+    )
+    .unwrap();
+    let token = created.token().unwrap();
+
+    let options = ClientOptions {
+        on_expired: ExpiredPolicy::Recreate,
+        reconcile_offset: true,
+        ..ClientOptions::default()
+    };
+    let meta = Client::new(options)
+        .resume(&UploadMeta::from_token(temp_file.path().into(), &token).unwrap())
+        .await
+        .unwrap();
+    assert!(meta.upload_complete());
+    assert_eq!(meta.remote_url.unwrap().path(), "/files/1");
+    let create = &server.requests_with_method("POST")[0];
+    assert_eq!(create.path, "/files/");
+}
+
+#[tokio::test]
+async fn should_resume_with_caller_reader() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(100);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::new(40));
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+
+    let content: Vec<u8> = (0..100).rev().collect();
+    let reader = std::io::Cursor::new(content.clone());
+    let result = client
+        .resume_with(&meta.with_bytes_uploaded(20), reader)
+        .await
+        .unwrap();
+    assert!(result.upload_complete());
+    let sent: Vec<u8> = server
+        .requests_with_method("PATCH")
+        .into_iter()
+        .flat_map(|request| request.body)
+        .collect();
+    assert_eq!(sent, content[20..]);
+}
+
+#[tokio::test]
+async fn should_reuse_upload_for_repeated_idempotent_create() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "HEAD" => MockResponse::new(200).header("upload-offset", "64"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        idempotent_create: true,
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let first = client.create(&path, &server.url, None, None).await.unwrap();
+    let second = client.create(&path, &server.url, None, None).await.unwrap();
+    assert_eq!(second.status.bytes_uploaded, 64);
+    assert_eq!(first.remote_url, second.remote_url);
+
+    let creates = server.requests_with_method("POST");
+    let key = creates[0].header("idempotency-key");
+    assert!(key.is_some());
+    assert_eq!(creates[1].header("idempotency-key"), key);
+}
+
+#[tokio::test]
+async fn should_classify_connection_failures() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+    let client = Client::new(ClientOptions::default());
+    let url = Url::parse(&format!("http://{addr}/files/")).unwrap();
+    let result = client.get_server_info(&url).await;
+    assert!(matches!(result, Err(TusError::ConnectFailed(_))));
+}
+
+#[tokio::test]
+async fn should_classify_tls_failures() {
+    let server = MockServer::tus().await;
+    let mut url = server.url.clone();
+    url.set_scheme("https").unwrap();
+    let client = Client::new(ClientOptions::default());
+    let result = client.get_server_info(&url).await;
+    assert!(matches!(result, Err(TusError::Tls(_))), "{result:?}");
+}
+
+#[tokio::test]
+async fn should_resend_chunk_from_server_offset_after_connection_reset() {
+    // The first PATCH stores 32 bytes, then the server goes away without responding
+    let stored = Arc::new(Mutex::new(None));
+    let offset = stored.clone();
+    let server = MockServer::start(move |req| match req.method.as_str() {
+        "PATCH" if offset.lock().unwrap().is_none() => {
+            *offset.lock().unwrap() = Some(32);
+            MockResponse::close()
+        }
+        "HEAD" => MockResponse::new(200)
+            .header("tus-resumable", "1.0.0")
+            .header(
+                "upload-offset",
+                &offset.lock().unwrap().unwrap().to_string(),
+            ),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = client.upload(&path, &server.url, None, None).await.unwrap();
+    assert!(meta.upload_complete());
+
+    let methods: Vec<_> = server.requests().into_iter().map(|r| r.method).collect();
+    assert_eq!(methods, vec!["POST", "PATCH", "HEAD", "PATCH"]);
+    let resent = &server.requests_with_method("PATCH")[1];
+    assert_eq!(resent.header("upload-offset"), Some("32"));
+    assert_eq!(resent.body.len(), 96);
+
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "PATCH" => MockResponse::close(),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let options = ClientOptions {
+        connection_reset_retries: 1,
+        ..ClientOptions::default()
+    };
+    let result = Client::new(options)
+        .upload(&path, &server.url, None, None)
+        .await;
+    assert!(
+        matches!(result, Err(TusError::ConnectionReset(_))),
+        "{result:?}"
+    );
+    assert_eq!(server.requests_with_method("PATCH").len(), 2);
+}
+
+#[tokio::test]
+async fn should_not_resend_chunk_after_malformed_response() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "PATCH" => MockResponse::new(204).header("bad header", "1"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let result = Client::new(ClientOptions::default())
+        .upload(&path, &server.url, None, None)
+        .await;
+    assert!(
+        matches!(result, Err(TusError::RequestError(_))),
+        "{result:?}"
+    );
+    assert_eq!(server.requests_with_method("PATCH").len(), 1);
+    assert!(server.requests_with_method("HEAD").is_empty());
+}
+
+#[tokio::test]
+async fn should_publish_progress_to_channel() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(100);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::new(40));
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+
+    let (sender, mut receiver) = tokio::sync::watch::channel(meta.status.clone());
+    let watcher = tokio::spawn(async move {
+        let mut seen = vec![];
+        while receiver.changed().await.is_ok() {
+            seen.push(receiver.borrow_and_update().bytes_uploaded);
+        }
+        seen
+    });
+    let result = client.resume_with_channel(&meta, sender).await.unwrap();
+    assert!(result.upload_complete());
+    let seen = watcher.await.unwrap();
+    assert_eq!(seen.last(), Some(&100));
+}
+
+#[tokio::test]
+async fn should_reject_file_larger_than_server_max_size() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "OPTIONS" => common::tus_handler(req).header("tus-max-size", "64"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let client = Client::new(ClientOptions::default());
+    let result = client.check_file(temp_file.path(), &server.url).await;
+    assert!(matches!(result, Err(TusError::FileTooLarge(_))));
+
+    let small_file = create_temp_file(32);
+    let info = client.check_file(small_file.path(), &server.url).await;
+    assert_eq!(info.unwrap().max_size, Some(64));
+}
+
+#[tokio::test]
+async fn should_upload_byte_range() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::new(16));
+    let result = client
+        .upload_range(&path, &server.url, 32..96, None, None)
+        .await
+        .unwrap();
+    assert_eq!(result.status.bytes_uploaded, 64);
+
+    let create = &server.requests_with_method("POST")[0];
+    assert_eq!(create.header("upload-length"), Some("64"));
+    let sent: Vec<u8> = server
+        .requests_with_method("PATCH")
+        .into_iter()
+        .flat_map(|request| request.body)
+        .collect();
+    let contents = std::fs::read(temp_file.path()).unwrap();
+    assert_eq!(sent, contents[32..96]);
+}
+
+#[tokio::test]
+async fn should_reject_out_of_bounds_range() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let result = client
+        .upload_range(&path, &server.url, 64..256, None, None)
+        .await;
+    assert!(matches!(result, Err(TusError::InvalidRange(_))));
+    assert!(server.requests().is_empty());
+}
+
+#[tokio::test]
+async fn should_recreate_expired_upload_when_configured() {
+    let server = MockServer::start(|req| match req.path.as_str() {
+        "/files/expired" => MockResponse::new(404),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let expired = UploadMeta::resuming(
+        path,
+        server.url.clone(),
+        server.url.join("expired").unwrap(),
+    )
+    .unwrap()
+    .with_bytes_uploaded(64);
+
+    let client = Client::new(ClientOptions::default());
+    let result = client.resume(&expired).await;
+    assert!(matches!(result, Err(TusError::NotFoundError(_))));
+
+    let options = ClientOptions {
+        on_expired: ExpiredPolicy::Recreate,
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let result = client.resume(&expired).await.unwrap();
+    assert!(result.upload_complete());
+    assert_eq!(result.remote_url.unwrap().path(), "/files/1");
+    let last = server.requests_with_method("PATCH").pop().unwrap();
+    assert_eq!(last.header("upload-offset"), Some("0"));
+    assert_eq!(last.body.len(), 128);
+}
+
+#[tokio::test]
+async fn should_upload_from_path_and_string_host() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let client = Client::new(ClientOptions::default());
+    let result = client
+        .upload_file(temp_file.path(), server.url.as_str(), None, None)
+        .await
+        .unwrap();
+    assert!(result.upload_complete());
+
+    let result = client
+        .upload_file(temp_file.path(), "not a url", None, None)
+        .await;
+    assert!(matches!(result, Err(TusError::StringParseError(_))));
+}
+
+#[tokio::test]
+async fn should_inspect_an_upload_by_url() {
+    let server = MockServer::start(|req| match req.path.as_str() {
+        "/files/deferred" => MockResponse::new(200)
+            .header("upload-offset", "64")
+            .header("upload-defer-length", "1"),
+        _ => MockResponse::new(200)
+            .header("upload-offset", "64")
+            .header("upload-length", "256"),
+    })
+    .await;
+    let client = Client::new(ClientOptions::default());
+
+    let status = client
+        .inspect(&server.url.join("1").unwrap())
+        .await
+        .unwrap();
+    assert_eq!(status.bytes_uploaded, 64);
+    assert_eq!(status.size, 256);
+    assert!(!status.length_deferred);
+
+    let url = server.url.join("/files/deferred").unwrap();
+    let status = client.inspect(&url).await.unwrap();
+    assert_eq!(status.bytes_uploaded, 64);
+    assert!(status.length_deferred);
+
+    let request = &server.requests_with_method("HEAD")[0];
+    assert_eq!(request.header("tus-resumable"), Some("1.0.0"));
+}
+
+#[tokio::test]
+async fn should_return_parsed_headers_of_head_request() {
+    let server = MockServer::start(|_| {
+        MockResponse::new(200)
+            .header("tus-resumable", "1.0.0")
+            .header("upload-offset", "64")
+            .header("upload-length", "256")
+            .header(
+                "upload-metadata",
+                "filename d29ybGRfZG9taW5hdGlvbl9wbGFuLnBkZg==",
+            )
+            .header("upload-concat", "partial")
+    })
+    .await;
+    let client = Client::new(ClientOptions::default());
+
+    let headers = client
+        .head_raw(&server.url.join("1").unwrap())
+        .await
+        .unwrap();
+    assert_eq!(headers.offset, Some(64));
+    assert_eq!(headers.upload_length, Some(256));
+    assert_eq!(headers.version, Some(TusVersion::V1_0_0));
+    assert_eq!(headers.concat, Some(UploadConcat::Partial));
+    assert_eq!(
+        headers.upload_metadata.unwrap()["filename"],
+        "world_domination_plan.pdf"
+    );
+    assert_eq!(server.requests_with_method("HEAD").len(), 1);
+}
+
+#[tokio::test]
+async fn should_sync_meta_with_server() {
+    let server = MockServer::start(|req| match (req.method.as_str(), req.path.as_str()) {
+        ("HEAD", "/files/1") => MockResponse::new(200)
+            .header("upload-offset", "64")
+            .header("upload-length", "128"),
+        ("HEAD", _) => MockResponse::new(410),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+
+    let synced = client.sync(&meta).await.unwrap();
+    assert_eq!(synced.status.bytes_uploaded, 64);
+    assert_eq!(synced.status.size, 128);
+    let meta = client.resume(&synced).await.unwrap();
+    assert!(meta.upload_complete());
+    assert_eq!(server.requests_with_method("PATCH")[0].body.len(), 64);
+
+    let gone = meta.with_remote_dest(server.url.join("2").unwrap().to_string());
+    let result = client.sync(&gone.unwrap()).await;
+    assert!(matches!(result, Err(TusError::NotFoundError(_))));
+}
+
+#[tokio::test]
+async fn should_sync_meta_with_custom_headers() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        _ if req.header("authorization") != Some("Bearer upload") => MockResponse::new(401),
+        "HEAD" => MockResponse::new(200)
+            .header("upload-offset", "64")
+            .header("upload-length", "128"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let custom = HashMap::from([("authorization".to_string(), "Bearer upload".to_string())]);
+    let meta = client
+        .create(&path, &server.url, None, Some(custom))
+        .await
+        .unwrap();
+
+    let synced = client.sync(&meta).await.unwrap();
+    assert_eq!(synced.status.bytes_uploaded, 64);
+    let head = &server.requests_with_method("HEAD")[0];
+    assert_eq!(head.header("tus-resumable"), Some("1.0.0"));
+}
+
+#[tokio::test]
+async fn should_send_creation_request_to_creation_path() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "POST" => {
+            let host = req.header("host").unwrap_or_default();
+            MockResponse::new(201).header("location", &format!("http://{host}/uploads/abc"))
+        }
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = UploadMeta::new(path, server.url.clone(), None, None, None)
+        .unwrap()
+        .with_creation_path("/api/create/")
+        .unwrap();
+    let meta = client.create_from(&meta).await.unwrap();
+    let meta = client.resume(&meta).await.unwrap();
+    assert!(meta.upload_complete());
+
+    assert_eq!(server.requests_with_method("POST")[0].path, "/api/create/");
+    assert_eq!(server.requests_with_method("PATCH")[0].path, "/uploads/abc");
+}
+
+#[tokio::test]
+async fn should_read_upload_url_from_alternative_location_headers() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "POST" => {
+            let host = req.header("host").unwrap_or_default();
+            MockResponse::new(201)
+                .header("tus-location", &format!("http://{host}/files/tus"))
+                .header("x-upload-url", &format!("http://{host}/files/custom"))
+        }
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+
+    let client = Client::new(ClientOptions::default());
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+    assert_eq!(meta.remote_url.unwrap().path(), "/files/tus");
+
+    let options = ClientOptions {
+        location_header: Some("X-Upload-Url".to_string()),
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+    assert_eq!(meta.remote_url.unwrap().path(), "/files/custom");
+}
+
+#[tokio::test]
+async fn should_fail_create_without_any_location_header() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "POST" => MockResponse::new(201),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        location_header: Some("x-upload-url".to_string()),
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let result = client.create(&path, &server.url, None, None).await;
+    assert!(matches!(result, Err(TusError::MissingHeader(name)) if name == "x-upload-url"));
+}
+
+#[tokio::test]
+async fn should_reject_cross_host_location_when_disallowed() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "POST" => MockResponse::new(201).header("location", "http://internal.example/files/1"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let strict = ClientOptions {
+        allow_cross_host_location: false,
+        ..ClientOptions::default()
+    };
+    let result = Client::new(strict.clone())
+        .create(&path, &server.url, None, None)
+        .await;
+    assert!(
+        matches!(result, Err(TusError::CrossHostLocation(ref message)) if message.contains("internal.example")),
+        "{result:?}"
+    );
+
+    // Allowed by default
+    let client = Client::new(ClientOptions::default());
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+    assert_eq!(
+        meta.require_url().unwrap().host_str(),
+        Some("internal.example")
+    );
+
+    // The same host is always accepted
+    let server = MockServer::tus().await;
+    let meta = Client::new(strict)
+        .create(&path, &server.url, None, None)
+        .await
+        .unwrap();
+    assert_eq!(meta.require_url().unwrap().path(), "/files/1");
+}
+
+#[tokio::test]
+async fn should_stream_status_after_each_chunk() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(100);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::new(40));
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+
+    let stream = client.resume_stream(&meta);
+    let offsets: Vec<_> = stream
+        .map(|status| status.unwrap().bytes_uploaded)
+        .collect()
+        .await;
+    assert_eq!(offsets, vec![40, 80, 100]);
+}
+
+#[tokio::test]
+async fn should_end_stream_after_first_error() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "PATCH" => MockResponse::new(500),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(100);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::new(40));
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+
+    let results: Vec<_> = client.resume_stream(&meta).collect().await;
+    assert_eq!(results.len(), 1);
+    assert!(results[0].is_err());
+}
+
+#[tokio::test]
+async fn should_keep_filtered_response_headers_when_enabled() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "PATCH" => common::tus_handler(req)
+            .header("etag", "\"abc\"")
+            .header("set-cookie", "session=secret")
+            .header("x-request-id", "42"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+
+    let client = Client::new(ClientOptions::default());
+    let meta = client.upload(&path, &server.url, None, None).await.unwrap();
+    assert!(meta.last_response_headers.is_none());
+
+    let options = ClientOptions {
+        keep_response_headers: true,
+        filtered_response_headers: vec!["Set-Cookie".to_string(), "X-Request-Id".to_string()],
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let meta = client.upload(&path, &server.url, None, None).await.unwrap();
+    let headers = meta.last_response_headers.unwrap();
+    assert_eq!(headers.get("etag").map(String::as_str), Some("\"abc\""));
+    assert_eq!(
+        headers.get("upload-offset").map(String::as_str),
+        Some("128")
+    );
+    assert!(!headers.contains_key("set-cookie"));
+    assert!(!headers.contains_key("x-request-id"));
+}
+
+#[tokio::test]
+async fn should_mirror_file_to_every_host() {
+    let first = MockServer::tus().await;
+    let second = MockServer::tus().await;
+    let failing = MockServer::start(|req| match req.method.as_str() {
+        "POST" => MockResponse::new(500),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(100);
+    let path = temp_file.path().into();
+    let hosts = [first.url.clone(), failing.url.clone(), second.url.clone()];
+
+    // Larger than one chunk, and small enough to be shared from memory
+    for chunksize in [40, 1024] {
+        let client = Client::new(ClientOptions::new(chunksize));
+        let results = client.mirror(&path, &hosts, None, None).await;
+        assert_eq!(results.len(), 3);
+        assert!(results[0].as_ref().unwrap().upload_complete());
+        assert!(results[1].is_err());
+        assert!(results[2].as_ref().unwrap().upload_complete());
+    }
+    for server in [&first, &second] {
+        let sent: usize = server
+            .requests_with_method("PATCH")
+            .iter()
+            .map(|r| r.body.len())
+            .sum();
+        assert_eq!(sent, 200);
+    }
+}
+
+#[tokio::test]
+async fn should_terminate_already_deleted_upload_when_404_is_success() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "DELETE" => MockResponse::new(404).body("gone"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+
+    client.terminate(&meta).await.unwrap();
+    assert_eq!(server.requests_with_method("DELETE").len(), 1);
+}
+
+#[tokio::test]
+async fn should_fail_to_terminate_already_deleted_upload_when_404_is_not_success() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "DELETE" => MockResponse::new(404).body("gone"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        terminate_treats_404_as_success: false,
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+
+    let result = client.terminate(&meta).await;
+    assert_eq!(result, Err(TusError::NotFoundError("gone".to_string())));
+}
+
+#[tokio::test]
+async fn should_terminate_many_uploads_tolerating_missing_ones() {
+    let server = MockServer::start(|req| match (req.method.as_str(), req.path.as_str()) {
+        ("DELETE", "/files/gone") => MockResponse::new(404),
+        ("DELETE", "/files/broken") => MockResponse::new(500),
+        ("DELETE", _) => MockResponse::new(204),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+    let at = |id: &str| {
+        meta.with_remote_dest(server.url.join(id).unwrap().to_string())
+            .unwrap()
+    };
+    let not_created = UploadMeta::new(path, server.url.clone(), None, None, None).unwrap();
+
+    let metas = [meta.clone(), at("gone"), at("broken"), not_created];
+    let results = client.terminate_many(&metas).await;
+    assert!(results[0].is_ok());
+    assert!(results[1].is_ok());
+    assert!(results[2].is_err());
+    assert!(matches!(results[3], Err(TusError::MissingUploadUrl)));
+    assert_eq!(server.requests_with_method("DELETE").len(), 3);
+}
+
+#[tokio::test]
+async fn should_restore_metadata_returned_by_server() {
+    let server = MockServer::start(|req| match (req.method.as_str(), req.path.as_str()) {
+        ("HEAD", "/files/1") => MockResponse::new(200)
+            .header("upload-offset", "64")
+            .header("upload-metadata", "filename Zm9v,owner YWxpY2U="),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let local = HashMap::from([
+        ("owner".to_string(), "bob".to_string()),
+        ("tag".to_string(), "x".to_string()),
+    ]);
+    let restored = |restore_metadata| {
+        let client = Client::new(ClientOptions {
+            restore_metadata,
+            ..ClientOptions::default()
+        });
+        let (path, server, local) = (&path, &server, local.clone());
+        async move {
+            let meta = client
+                .create(path, &server.url, Some(local), None)
+                .await
+                .unwrap();
+            let meta = client.get_offset(&meta).await.unwrap();
+            assert_eq!(meta.status.bytes_uploaded, 64);
+            meta.extra_meta.unwrap()
+        }
+    };
+
+    assert_eq!(restored(MetadataRestore::Ignore).await, local);
+    let merged = restored(MetadataRestore::Merge).await;
+    assert_eq!(merged.len(), 3);
+    assert_eq!(merged["filename"], "foo");
+    assert_eq!(merged["owner"], "bob");
+    let replaced = restored(MetadataRestore::Replace).await;
+    assert_eq!(replaced.len(), 2);
+    assert_eq!(replaced["owner"], "alice");
+}
+
+#[tokio::test]
+async fn should_keep_metadata_when_server_returns_none() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions {
+        restore_metadata: MetadataRestore::Replace,
+        ..ClientOptions::default()
+    });
+    let local = HashMap::from([("owner".to_string(), "bob".to_string())]);
+    let meta = client
+        .create(&path, &server.url, Some(local.clone()), None)
+        .await
+        .unwrap();
+    let meta = client.get_offset(&meta).await.unwrap();
+    assert_eq!(meta.extra_meta, Some(local));
+}
+
+#[tokio::test]
+async fn should_retry_locked_uploads() {
+    let locked = Arc::new(Mutex::new(2));
+    let remaining = locked.clone();
+    let server = MockServer::start(move |req| match req.method.as_str() {
+        "PATCH" if *remaining.lock().unwrap() > 0 => {
+            *remaining.lock().unwrap() -= 1;
+            MockResponse::new(423)
+        }
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        locked_retry_delay: Duration::from_millis(1),
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let meta = client.upload(&path, &server.url, None, None).await.unwrap();
+    assert!(meta.upload_complete());
+    assert_eq!(server.requests_with_method("PATCH").len(), 3);
+}
+
+#[tokio::test]
+async fn should_fail_when_upload_stays_locked() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "PATCH" => MockResponse::new(423).body("busy"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        locked_retries: 2,
+        locked_retry_delay: Duration::from_millis(1),
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let result = client.upload(&path, &server.url, None, None).await;
+    assert!(matches!(result, Err(TusError::Locked(body)) if body == "busy"));
+    assert_eq!(server.requests_with_method("PATCH").len(), 3);
+}
+
+#[tokio::test]
+async fn should_open_new_connection_per_request_without_idle_pool() {
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    for (max_idle, connections) in [(None, 1), (Some(0), 3)] {
+        let server = MockServer::tus().await;
+        let options = ClientOptions {
+            chunksize: 64,
+            pool_max_idle_per_host: max_idle,
+            pool_idle_timeout: Some(Duration::from_secs(30)),
+            ..ClientOptions::default()
+        };
+        let client = Client::new(options);
+        client.upload(&path, &server.url, None, None).await.unwrap();
+        assert_eq!(server.requests().len(), 3);
+        assert_eq!(server.connections(), connections);
+    }
+}
+
+#[tokio::test]
+async fn should_send_origin_with_every_request() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        origin: Some("https://app.example.com".to_string()),
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    client.get_server_info(&server.url).await.unwrap();
+    client.upload(&path, &server.url, None, None).await.unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    assert!(requests
+        .iter()
+        .all(|r| r.header("origin") == Some("https://app.example.com")));
+}
+
+#[tokio::test]
+async fn should_send_default_headers_with_every_request() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        default_headers: HashMap::from([
+            ("Authorization".to_string(), "Bearer default".to_string()),
+            ("X-Tenant".to_string(), "acme".to_string()),
+            ("Tus-Resumable".to_string(), "0.0.1".to_string()),
+        ]),
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    client.get_server_info(&server.url).await.unwrap();
+    let custom = HashMap::from([("authorization".to_string(), "Bearer upload".to_string())]);
+    client
+        .upload(&path, &server.url, None, Some(custom))
+        .await
+        .unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    assert!(requests
+        .iter()
+        .all(|r| r.header("x-tenant") == Some("acme")));
+    assert_eq!(requests[0].header("authorization"), Some("Bearer default"));
+    for request in &requests[1..] {
+        assert_eq!(request.header("authorization"), Some("Bearer upload"));
+        assert_eq!(request.header("tus-resumable"), Some("1.0.0"));
+    }
+}
+
+#[tokio::test]
+async fn should_verify_upload_against_server_checksum() {
+    use base64::Engine;
+    use sha2::Digest;
+
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let digest = sha2::Sha256::digest(std::fs::read(temp_file.path()).unwrap());
+    let checksum = format!(
+        "sha256 {}",
+        base64::engine::general_purpose::STANDARD.encode(digest)
+    );
+    let server = MockServer::start(move |req| match (req.method.as_str(), req.path.as_str()) {
+        ("HEAD", "/files/1") => common::tus_handler(req).header("upload-checksum", &checksum),
+        ("HEAD", "/files/other") => {
+            common::tus_handler(req).header("upload-checksum", "sha1 AAAAAAAAAAAAAAAAAAAAAAAAAAA=")
+        }
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let client = Client::new(ClientOptions::default());
+    let meta = client.upload(&path, &server.url, None, None).await.unwrap();
+    assert!(client.verify_upload(&meta).await.unwrap());
+
+    let other = meta
+        .with_remote_dest(server.url.join("other").unwrap().to_string())
+        .unwrap();
+    assert!(!client.verify_upload(&other).await.unwrap());
+
+    let unsupported = meta
+        .with_remote_dest(server.url.join("plain").unwrap().to_string())
+        .unwrap();
+    let result = client.verify_upload(&unsupported).await;
+    assert!(matches!(result, Err(TusError::VerificationUnsupported)));
+}
+
+#[tokio::test]
+async fn should_verify_upload_with_custom_headers_while_locked() {
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let locked = Arc::new(Mutex::new(true));
+    let server = MockServer::start(move |req| match req.method.as_str() {
+        _ if req.header("authorization") != Some("Bearer upload") => MockResponse::new(401),
+        "HEAD" if std::mem::replace(&mut *locked.lock().unwrap(), false) => MockResponse::new(423),
+        "HEAD" => {
+            common::tus_handler(req).header("upload-checksum", "sha1 AAAAAAAAAAAAAAAAAAAAAAAAAAA=")
+        }
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let client = Client::new(ClientOptions {
+        locked_retry_delay: Duration::from_millis(1),
+        ..ClientOptions::default()
+    });
+    let custom = HashMap::from([("authorization".to_string(), "Bearer upload".to_string())]);
+    let meta = client
+        .upload(&path, &server.url, None, Some(custom))
+        .await
+        .unwrap();
+
+    assert!(!client.verify_upload(&meta).await.unwrap());
+    let heads = server.requests_with_method("HEAD");
+    assert_eq!(heads.len(), 2);
+    assert!(heads
+        .iter()
+        .all(|r| r.header("tus-resumable") == Some("1.0.0")));
+}
+
+#[tokio::test]
+async fn should_resume_from_seeded_offset() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let client = Client::new(ClientOptions::default());
+    let remote_url = server.url.join("1").unwrap();
+    let meta = UploadMeta::resuming(temp_file.path().into(), server.url.clone(), remote_url)
+        .unwrap()
+        .with_bytes_uploaded(100);
+
+    let result = client.resume(&meta).await.unwrap();
+    assert!(result.upload_complete());
+    assert!(server.requests_with_method("HEAD").is_empty());
+    let patch = &server.requests_with_method("PATCH")[0];
+    assert_eq!(patch.header("upload-offset"), Some("100"));
+    let file = std::fs::read(temp_file.path()).unwrap();
+    assert_eq!(patch.body, file[100..]);
+}
+
+#[tokio::test]
+async fn should_reconcile_seeded_offset_with_server() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "HEAD" => MockResponse::new(200).header("upload-offset", "64"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let options = ClientOptions {
+        reconcile_offset: true,
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let remote_url = server.url.join("1").unwrap();
+    let meta = UploadMeta::resuming(temp_file.path().into(), server.url.clone(), remote_url)
+        .unwrap()
+        .with_bytes_uploaded(100);
+
+    client.resume(&meta).await.unwrap();
+    let patch = &server.requests_with_method("PATCH")[0];
+    assert_eq!(patch.header("upload-offset"), Some("64"));
+}
+
+#[tokio::test]
+async fn should_send_content_length_of_each_chunk() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(100);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::new(40));
+    client.upload(&path, &server.url, None, None).await.unwrap();
+
+    let patches = server.requests_with_method("PATCH");
+    let lengths: Vec<_> = patches
+        .iter()
+        .map(|r| r.header("content-length").unwrap())
+        .collect();
+    assert_eq!(lengths, vec!["40", "40", "20"]);
+    assert!(patches
+        .iter()
+        .all(|r| r.header("transfer-encoding").is_none()));
+}
+
+#[tokio::test]
+async fn should_give_up_after_too_many_errors_across_resumes() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "PATCH" => MockResponse::new(500),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let checkpoint_dir = tempfile::tempdir().unwrap();
+    let options = ClientOptions {
+        checkpoint_path: Some(checkpoint_dir.path().join("upload.json")),
+        max_total_errors: Some(2),
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+
+    for _ in 0..2 {
+        let result = client.resume(&meta).await;
+        match result {
+            Err(TusError::ChunkFailed { source, .. }) => {
+                assert!(matches!(*source, TusError::ServerError { status: 500, .. }))
+            }
+            result => panic!("unexpected result {result:?}"),
+        }
+    }
+    let failed = match client.resume(&meta).await {
+        Err(TusError::TooManyErrors { count: 3, meta }) => meta,
+        result => panic!("unexpected result {result:?}"),
+    };
+    assert_eq!(failed.error_count, 3);
+    assert_eq!(server.requests_with_method("PATCH").len(), 3);
+
+    // Reusing the meta gives up before sending anything
+    let client = Client::new(ClientOptions {
+        max_total_errors: Some(2),
+        ..ClientOptions::default()
+    });
+    let result = client.resume(&failed).await;
+    assert!(matches!(
+        result,
+        Err(TusError::TooManyErrors { count: 3, .. })
+    ));
+    assert_eq!(server.requests_with_method("PATCH").len(), 3);
+}
+
+#[tokio::test]
+async fn should_count_errors_of_recovery_requests() {
+    // The offset can't be fetched after a 409, nor the upload created again after a 404
+    let server = MockServer::start(|req| match (req.method.as_str(), req.path.as_str()) {
+        ("PATCH", "/files/expired") => MockResponse::new(404),
+        ("PATCH", _) => MockResponse::new(409),
+        ("HEAD" | "POST", _) => MockResponse::new(500),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path: std::path::PathBuf = temp_file.path().into();
+    let client = Client::new(ClientOptions {
+        max_total_errors: Some(1),
+        on_expired: ExpiredPolicy::Recreate,
+        ..ClientOptions::default()
+    });
+    let resuming = |upload| {
+        UploadMeta::resuming(
+            path.clone(),
+            server.url.clone(),
+            server.url.join(upload).unwrap(),
+        )
+        .unwrap()
+    };
+
+    let meta = match client.resume(&resuming("1")).await {
+        Err(TusError::ChunkFailed { source, meta }) => {
+            assert!(matches!(*source, TusError::ServerError { status: 500, .. }));
+            meta
+        }
+        result => panic!("unexpected result {result:?}"),
+    };
+    assert_eq!(meta.error_count, 1);
+    assert_eq!(server.requests_with_method("HEAD").len(), 1);
+
+    let expired = UploadMeta {
+        error_count: 1,
+        ..resuming("expired")
+    };
+    let result = client.resume(&expired).await;
+    assert!(matches!(
+        result,
+        Err(TusError::TooManyErrors { count: 2, .. })
+    ));
+    assert_eq!(server.requests_with_method("POST").len(), 1);
+}
+
+#[tokio::test]
+async fn should_count_errors_across_resumes_of_returned_meta() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "PATCH" => MockResponse::new(500),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions {
+        max_total_errors: Some(1),
+        ..ClientOptions::default()
+    });
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+
+    let meta = match client.resume(&meta).await {
+        Err(TusError::ChunkFailed { meta, .. }) => meta,
+        result => panic!("unexpected result {result:?}"),
+    };
+    assert_eq!(meta.error_count, 1);
+    let result = client.resume(&meta).await;
+    assert!(matches!(
+        result,
+        Err(TusError::TooManyErrors { count: 2, .. })
+    ));
+    assert_eq!(server.requests_with_method("PATCH").len(), 2);
+}
+
+#[tokio::test]
+async fn should_pause_and_resume_background_upload() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::new(16));
+    let upload = client.start(&path, &server.url, None, None).await.unwrap();
+    upload.pause();
+    assert!(upload.is_paused());
+
+    // At most the chunk in flight when pausing is sent
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let sent = server.requests_with_method("PATCH").len();
+    assert!(sent <= 1);
+    assert_eq!(upload.status().bytes_uploaded, sent * 16);
+
+    upload.resume();
+    let meta = upload.wait().await.unwrap();
+    assert!(meta.upload_complete());
+    assert_eq!(server.requests_with_method("PATCH").len(), 8);
+}
+
+#[tokio::test]
+async fn should_cancel_background_upload_and_terminate_it() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::new(16));
+    let upload = client.start(&path, &server.url, None, None).await.unwrap();
+    upload.pause();
+    upload.cancel(true).await.unwrap();
+
+    assert!(server.requests_with_method("PATCH").len() <= 1);
+    let delete = &server.requests_with_method("DELETE")[0];
+    assert_eq!(delete.path, "/files/1");
+}
+
+#[tokio::test]
+async fn should_cache_server_info_within_ttl() {
+    let server = MockServer::tus().await;
+    let options = ClientOptions {
+        server_info_ttl: Some(Duration::from_millis(200)),
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    client.get_server_info(&server.url).await.unwrap();
+    client.clone().get_server_info(&server.url).await.unwrap();
+    assert_eq!(server.requests_with_method("OPTIONS").len(), 1);
+
+    client.invalidate_server_info(&server.url);
+    client.get_server_info(&server.url).await.unwrap();
+    assert_eq!(server.requests_with_method("OPTIONS").len(), 2);
+
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    client.get_server_info(&server.url).await.unwrap();
+    assert_eq!(server.requests_with_method("OPTIONS").len(), 3);
+}
+
+#[tokio::test]
+async fn should_not_cache_server_info_by_default() {
+    let server = MockServer::tus().await;
+    let client = Client::new(ClientOptions::default());
+    client.get_server_info(&server.url).await.unwrap();
+    client.get_server_info(&server.url).await.unwrap();
+    assert_eq!(server.requests_with_method("OPTIONS").len(), 2);
+}
+
+/// Tus server which also supports Creation With Upload, storing the whole POST body
+fn creation_with_upload_handler(req: &common::RecordedRequest) -> MockResponse {
+    match req.method.as_str() {
+        "OPTIONS" => MockResponse::new(204)
+            .header("tus-resumable", "1.0.0")
+            .header("tus-version", "1.0.0")
+            .header("tus-extension", "creation,creation-with-upload"),
+        "POST" => common::tus_handler(req).header("upload-offset", &req.body.len().to_string()),
+        _ => common::tus_handler(req),
+    }
+}
+
+#[tokio::test]
+async fn should_send_small_files_with_the_creation_request() {
+    let server = MockServer::start(creation_with_upload_handler).await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let sent = Arc::new(Mutex::new(vec![]));
+    let audit = sent.clone();
+    let options = ClientOptions {
+        creation_with_upload: true,
+        server_info_ttl: Some(Duration::from_secs(60)),
+        ..ClientOptions::default()
+    }
+    .with_on_chunk_send(move |offset, len| audit.lock().unwrap().push((offset, len)));
+    let client = Client::new(options);
+    for _ in 0..2 {
+        let meta = client.upload(&path, &server.url, None, None).await.unwrap();
+        assert!(meta.upload_complete());
+        assert_eq!(meta.stats.strategy, UploadStrategy::CreationWithUpload);
+        assert_eq!(meta.stats.bytes_sent, 128);
+    }
+
+    assert_eq!(server.requests_with_method("OPTIONS").len(), 1);
+    assert!(server.requests_with_method("PATCH").is_empty());
+    assert_eq!(*sent.lock().unwrap(), vec![(0, 128), (0, 128)]);
+    let create = &server.requests_with_method("POST")[0];
+    assert_eq!(create.body, std::fs::read(temp_file.path()).unwrap());
+    assert_eq!(
+        create.header("content-type"),
+        Some("application/offset+octet-stream")
+    );
+}
+
+#[tokio::test]
+async fn should_not_send_files_with_the_creation_request_by_default() {
+    let server = MockServer::start(creation_with_upload_handler).await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        server_info_ttl: Some(Duration::from_secs(60)),
+        ..ClientOptions::default()
+    };
+    let meta = Client::new(options)
+        .upload(&path, &server.url, None, None)
+        .await
+        .unwrap();
+    assert_eq!(meta.stats.strategy, UploadStrategy::CreateThenPatch);
+    assert!(server.requests_with_method("OPTIONS").is_empty());
+    assert_eq!(server.requests_with_method("PATCH").len(), 1);
+}
+
+#[tokio::test]
+async fn should_continue_from_offset_returned_on_creation() {
+    // The server stores only part of the body sent with the creation request
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "POST" => common::tus_handler(req).header("upload-offset", "100"),
+        _ => creation_with_upload_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::default());
+    let meta = client.upload(&path, &server.url, None, None).await.unwrap();
+    assert!(meta.upload_complete());
+
+    let patches = server.requests_with_method("PATCH");
+    assert_eq!(patches.len(), 1);
+    assert_eq!(patches[0].header("upload-offset"), Some("100"));
+    assert_eq!(
+        patches[0].body,
+        std::fs::read(temp_file.path()).unwrap()[100..]
+    );
+}
+
+#[tokio::test]
+async fn should_patch_files_larger_than_a_chunk() {
+    let server = MockServer::start(creation_with_upload_handler).await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        chunksize: 64,
+        creation_with_upload: true,
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let meta = client.upload(&path, &server.url, None, None).await.unwrap();
+    assert_eq!(meta.stats.strategy, UploadStrategy::CreateThenPatch);
+    assert!(server.requests_with_method("POST")[0].body.is_empty());
+    assert_eq!(server.requests_with_method("PATCH").len(), 2);
+}
+
+/// Gateway which reports offsets in a JSON body instead of `Upload-Offset`
+fn body_offset_handler(req: &common::RecordedRequest) -> MockResponse {
+    match req.method.as_str() {
+        "PATCH" => {
+            let offset: usize = req
+                .header("upload-offset")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_default();
+            MockResponse::new(200)
+                .header("tus-resumable", "1.0.0")
+                .header("content-type", "application/json")
+                .body(&format!(
+                    r#"{{"upload": {{"offset": {}}}}}"#,
+                    offset + req.body.len()
+                ))
+        }
+        "HEAD" => MockResponse::new(200).header("tus-resumable", "1.0.0"),
+        _ => common::tus_handler(req),
+    }
+}
+
+#[tokio::test]
+async fn should_read_offset_from_body_when_configured() {
+    let server = MockServer::start(body_offset_handler).await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        chunksize: 64,
+        offset_body_pointer: Some("/upload/offset".to_string()),
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    let meta = client.upload(&path, &server.url, None, None).await.unwrap();
+    assert!(meta.upload_complete());
+    assert_eq!(server.requests_with_method("PATCH").len(), 2);
+}
+
+#[tokio::test]
+async fn should_require_offset_header_by_default() {
+    let server = MockServer::start(body_offset_handler).await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::new(64));
+    let result = client.upload(&path, &server.url, None, None).await;
+    assert!(matches!(result, Err(TusError::MissingHeader(header)) if header == "upload-offset"));
+}
+
+#[tokio::test]
+async fn should_report_every_chunk_sent_and_acknowledged() {
+    let locked = Arc::new(Mutex::new(1));
+    let remaining = locked.clone();
+    let server = MockServer::start(move |req| match req.method.as_str() {
+        "PATCH" if *remaining.lock().unwrap() > 0 => {
+            *remaining.lock().unwrap() -= 1;
+            MockResponse::new(423)
+        }
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let acked = Arc::new(Mutex::new(Vec::new()));
+    let (sent_log, acked_log) = (sent.clone(), acked.clone());
+    let options = ClientOptions {
+        chunksize: 64,
+        locked_retry_delay: Duration::from_millis(1),
+        ..ClientOptions::default()
+    }
+    .with_on_chunk_send(move |offset, len| sent_log.lock().unwrap().push((offset, len)))
+    .with_on_chunk_ack(move |offset| acked_log.lock().unwrap().push(offset));
+    let client = Client::new(options);
+    let meta = client.upload(&path, &server.url, None, None).await.unwrap();
+    assert!(meta.upload_complete());
+    assert_eq!(*sent.lock().unwrap(), vec![(0, 64), (0, 64), (64, 64)]);
+    assert_eq!(*acked.lock().unwrap(), vec![64, 128]);
+}
+
+#[cfg(feature = "gzip")]
+#[tokio::test]
+async fn should_upload_decompressed_content_of_gzip_file() {
+    use std::io::Write;
+
+    let server = MockServer::tus().await;
+    let content: Vec<u8> = (0..200).map(|i| (i % 7) as u8).collect();
+    let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&content).unwrap();
+    temp_file.write_all(&encoder.finish().unwrap()).unwrap();
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::new(64));
+    let meta = client
+        .upload_gzip(&path, &server.url, None, None)
+        .await
+        .unwrap();
+    assert!(meta.upload_complete());
+    assert_eq!(meta.status.size, 200);
+
+    let create = &server.requests_with_method("POST")[0];
+    assert_eq!(create.header("upload-defer-length"), Some("1"));
+    let patches = server.requests_with_method("PATCH");
+    let body: Vec<u8> = patches.iter().flat_map(|r| r.body.clone()).collect();
+    assert_eq!(body, content);
+    // The length is declared before the short final chunk
+    let declared: Vec<_> = patches.iter().map(|r| r.header("upload-length")).collect();
+    assert_eq!(declared, vec![None, None, None, Some("200"), None]);
+}
+
+#[cfg(feature = "gzip")]
+#[tokio::test]
+async fn should_resend_gzip_chunk_after_connection_reset() {
+    use std::io::Write;
+
+    let reset = Arc::new(Mutex::new(true));
+    let server = MockServer::start(move |req| match req.method.as_str() {
+        "PATCH" if req.header("upload-offset") == Some("64") && !req.body.is_empty() => {
+            match std::mem::replace(&mut *reset.lock().unwrap(), false) {
+                true => MockResponse::close(),
+                false => common::tus_handler(req),
+            }
+        }
+        "HEAD" => common::tus_handler(req).header("upload-offset", "64"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let content: Vec<u8> = (0..200).map(|i| (i % 7) as u8).collect();
+    let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&content).unwrap();
+    temp_file.write_all(&encoder.finish().unwrap()).unwrap();
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::new(64));
+    let meta = client
+        .upload_gzip(&path, &server.url, None, None)
+        .await
+        .unwrap();
+    assert!(meta.upload_complete());
+    assert_eq!(meta.status.size, 200);
+
+    let offsets: Vec<_> = server
+        .requests_with_method("PATCH")
+        .iter()
+        .map(|r| (r.header("upload-offset").unwrap().to_string(), r.body.len()))
+        .collect();
+    let expected = [
+        ("0", 64),
+        ("64", 64),
+        ("64", 64),
+        ("128", 64),
+        ("192", 0),
+        ("192", 8),
+    ];
+    let expected: Vec<_> = expected.iter().map(|(o, l)| (o.to_string(), *l)).collect();
+    assert_eq!(offsets, expected);
+}
+
+#[tokio::test]
+async fn should_change_chunksize_between_chunks() {
+    let server = MockServer::tus().await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let client = Client::new(ClientOptions::new(64));
+    let meta = client.create(&path, &server.url, None, None).await.unwrap();
+
+    let mut stream = Box::pin(client.resume_stream(&meta));
+    assert_eq!(stream.next().await.unwrap().unwrap().bytes_uploaded, 64);
+    client.set_chunksize(32).unwrap();
+    let offsets: Vec<_> = stream
+        .map(|status| status.unwrap().bytes_uploaded)
+        .collect()
+        .await;
+    assert_eq!(offsets, vec![96, 128]);
+    assert!(matches!(
+        client.set_chunksize(0),
+        Err(TusError::InvalidChunkSize(0))
+    ));
+}
+
+#[tokio::test]
+async fn should_reject_chunksize_above_server_max_size() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "OPTIONS" => common::tus_handler(req).header("tus-max-size", "100"),
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let options = ClientOptions {
+        server_info_ttl: Some(Duration::from_secs(60)),
+        ..ClientOptions::default()
+    };
+    let client = Client::new(options);
+    client.get_server_info(&server.url).await.unwrap();
+
+    assert!(matches!(
+        client.set_chunksize(101),
+        Err(TusError::FileTooLarge(_))
+    ));
+    client.set_chunksize(100).unwrap();
+}
+
+#[tokio::test]
+async fn should_fail_when_upload_changed_since_last_etag() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "POST" => common::tus_handler(req).header("etag", "\"v1\""),
+        // Another client wrote to the upload after the first chunk
+        "PATCH" if req.header("if-match") == Some("\"v1\"") => {
+            common::tus_handler(req).header("etag", "\"v2\"")
+        }
+        "PATCH" if req.header("if-match").is_some() => {
+            MockResponse::new(412).body("etag is \"v3\"")
+        }
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let options = ClientOptions {
+        conditional_requests: true,
+        ..ClientOptions::new(64)
+    };
+    let result = Client::new(options)
+        .upload(&path, &server.url, None, None)
+        .await;
+    assert_eq!(
+        result.unwrap_err(),
+        TusError::ResourceChanged("etag is \"v3\"".to_string())
+    );
+    let sent: Vec<_> = server
+        .requests_with_method("PATCH")
+        .iter()
+        .map(|r| r.header("if-match").map(str::to_string))
+        .collect();
+    assert_eq!(
+        sent,
+        vec![Some("\"v1\"".to_string()), Some("\"v2\"".to_string())]
+    );
+
+    // Without the option no If-Match is sent
+    let server = MockServer::start(|req| common::tus_handler(req).header("etag", "\"v1\"")).await;
+    let meta = Client::new(ClientOptions::default())
+        .upload(&path, &server.url, None, None)
+        .await
+        .unwrap();
+    assert_eq!(meta.etag, None);
+    let patch = &server.requests_with_method("PATCH")[0];
+    assert_eq!(patch.header("if-match"), None);
+}
@@ -0,0 +1,153 @@
+use reqwest::header::{HeaderMap, HeaderValue};
+use tus_rs::{
+    error::TusError,
+    tus::{
+        headers::TusHeaders, TusExtension, TusServerInfo, TusVersion, UploadConcat,
+        TUS_PROTOCOL_VERSION,
+    },
+};
+
+fn server_info(versions: &str) -> TusServerInfo {
+    let mut headers = HeaderMap::new();
+    headers.insert("tus-resumable", HeaderValue::from_static("1.0.0"));
+    headers.insert("tus-version", HeaderValue::from_str(versions).unwrap());
+    headers.into()
+}
+
+#[test]
+fn should_negotiate_common_version() {
+    let info = server_info("1.0.0, 0.2.2");
+    let result = info.negotiate_version(&["0.2.2", "1.0.0"]);
+    assert_eq!(result.unwrap(), "0.2.2");
+}
+
+#[test]
+fn should_reject_unsupported_version() {
+    let info = server_info("0.2.2");
+    let result = info.negotiate_version(&["1.0.0"]);
+    match result {
+        Err(TusError::UnsupportedVersion(versions)) => assert_eq!(versions, vec!["0.2.2"]),
+        other => panic!("unexpected result: {other:?}"),
+    }
+}
+
+#[test]
+fn should_parse_typed_versions() {
+    let info = server_info("1.0.0, 0.2.2, 0.1.0");
+    assert_eq!(info.version, Some(TusVersion::V1_0_0));
+    assert_eq!(
+        info.supported_versions,
+        [
+            TusVersion::V1_0_0,
+            TusVersion::V0_2_2,
+            TusVersion::Other("0.1.0".to_string())
+        ]
+    );
+    assert_eq!(
+        info.negotiate_version(&["0.2.2"]).unwrap(),
+        TusVersion::V0_2_2
+    );
+    assert_eq!(TusVersion::Other("0.1.0".to_string()).as_str(), "0.1.0");
+    assert_eq!(
+        serde_json::to_string(&TusVersion::V1_0_0).unwrap(),
+        "\"1.0.0\""
+    );
+    assert_eq!(TusVersion::from(TUS_PROTOCOL_VERSION), TusVersion::V1_0_0);
+}
+
+#[test]
+fn should_summarize_server_info() {
+    let mut headers = HeaderMap::new();
+    headers.insert("tus-resumable", HeaderValue::from_static("1.0.0"));
+    headers.insert("tus-max-size", HeaderValue::from_static("5368709120"));
+    headers.insert(
+        "tus-extension",
+        HeaderValue::from_static("creation,expiration"),
+    );
+    let info: TusServerInfo = headers.into();
+    assert_eq!(
+        info.to_string(),
+        "tus 1.0.0, max 5GB, ext: creation,expiration"
+    );
+
+    let mut headers = HeaderMap::new();
+    headers.insert("tus-max-size", HeaderValue::from_static("1572864"));
+    let info: TusServerInfo = headers.into();
+    assert_eq!(info.to_string(), "tus unknown, max 1.5MB, ext: none");
+}
+
+#[test]
+fn should_parse_tus_headers_without_panicking() {
+    let mut headers = HeaderMap::new();
+    headers.insert("tus-max-size", HeaderValue::from_static("lots"));
+    headers.insert("upload-offset", HeaderValue::from_static("64"));
+    headers.insert(
+        "tus-extension",
+        HeaderValue::from_static("creation, expiration"),
+    );
+    let parsed = TusHeaders::from_headers(&headers);
+    assert_eq!(parsed.max_size, None);
+    assert_eq!(parsed.offset, Some(64));
+
+    let info = TusServerInfo::from_headers(&headers);
+    assert_eq!(
+        info.extensions,
+        [TusExtension::Creation, TusExtension::Expiration]
+    );
+}
+
+#[test]
+fn should_check_size_against_max_size() {
+    let mut headers = HeaderMap::new();
+    headers.insert("tus-max-size", HeaderValue::from_static("1024"));
+    let info = TusServerInfo::from_headers(&headers);
+    assert!(info.can_upload(1024));
+    assert!(!info.can_upload(1025));
+    assert_eq!(info.remaining_capacity(1000), Some(24));
+    assert_eq!(info.remaining_capacity(2000), Some(0));
+    assert!(matches!(
+        info.check_size(2000),
+        Err(TusError::FileTooLarge(message)) if message.contains("1024")
+    ));
+
+    let unlimited = TusServerInfo::from_headers(&HeaderMap::new());
+    assert!(unlimited.can_upload(usize::MAX));
+    assert_eq!(unlimited.remaining_capacity(1), None);
+}
+
+#[test]
+fn should_parse_upload_concat_header() {
+    let concat = |value: &'static str| {
+        let mut headers = HeaderMap::new();
+        headers.insert("upload-concat", HeaderValue::from_static(value));
+        TusHeaders::from_headers(&headers).concat
+    };
+    assert_eq!(concat("partial"), Some(UploadConcat::Partial));
+    assert_eq!(
+        concat("final;http://tus.io/files/a http://tus.io/files/b"),
+        Some(UploadConcat::Final(vec![
+            "http://tus.io/files/a".parse().unwrap(),
+            "http://tus.io/files/b".parse().unwrap(),
+        ]))
+    );
+    assert_eq!(concat("whole"), None);
+    assert_eq!(TusHeaders::from_headers(&HeaderMap::new()).concat, None);
+}
+
+#[test]
+fn should_pick_best_supported_checksum() {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "tus-checksum-algorithm",
+        HeaderValue::from_static("sha1,md5"),
+    );
+    let info = TusServerInfo::from_headers(&headers);
+    assert_eq!(
+        info.best_checksum(&["sha256", "sha1", "md5"]),
+        Some("sha1".to_string())
+    );
+    assert_eq!(info.best_checksum(&["sha256"]), None);
+
+    let none = TusServerInfo::from_headers(&HeaderMap::new());
+    assert_eq!(none.best_checksum(&["sha256", "sha1"]), None);
+}
@@ -0,0 +1,163 @@
+#![cfg(feature = "tracing")]
+
+mod common;
+
+use common::{create_temp_file, MockResponse, MockServer};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Event, Metadata, Subscriber,
+};
+use tracing_core::span::Current;
+use tus_rs::client::{Client, ClientOptions};
+
+/// A span seen by the `Recorder`, with the fields recorded so far
+#[derive(Debug, Clone)]
+struct RecordedSpan {
+    metadata: &'static Metadata<'static>,
+    fields: HashMap<String, String>,
+}
+
+/// Subscriber which keeps every span and its fields, to assert on them
+#[derive(Default, Clone)]
+struct Recorder {
+    spans: Arc<Mutex<Vec<RecordedSpan>>>,
+    entered: Arc<Mutex<Vec<Id>>>,
+}
+
+impl Recorder {
+    fn spans(&self, name: &str) -> Vec<HashMap<String, String>> {
+        self.spans
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|span| span.metadata.name() == name)
+            .map(|span| span.fields.clone())
+            .collect()
+    }
+}
+
+struct Fields<'a>(&'a mut HashMap<String, String>);
+
+impl Visit for Fields<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{value:?}"));
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes) -> Id {
+        let mut fields = HashMap::new();
+        span.record(&mut Fields(&mut fields));
+        let mut spans = self.spans.lock().unwrap();
+        spans.push(RecordedSpan {
+            metadata: span.metadata(),
+            fields,
+        });
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &Id, values: &Record) {
+        let mut spans = self.spans.lock().unwrap();
+        let span = &mut spans[span.into_u64() as usize - 1];
+        values.record(&mut Fields(&mut span.fields));
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, _: &Event) {}
+
+    fn enter(&self, span: &Id) {
+        self.entered.lock().unwrap().push(span.clone());
+    }
+
+    fn exit(&self, _: &Id) {
+        self.entered.lock().unwrap().pop();
+    }
+
+    fn current_span(&self) -> Current {
+        match self.entered.lock().unwrap().last() {
+            Some(id) => {
+                let metadata = self.spans.lock().unwrap()[id.into_u64() as usize - 1].metadata;
+                Current::new(id.clone(), metadata)
+            }
+            None => Current::none(),
+        }
+    }
+}
+
+#[tokio::test]
+async fn should_record_each_operation_in_its_span() {
+    let locked = Arc::new(Mutex::new(1));
+    let server = MockServer::start(move |req| match req.method.as_str() {
+        "PATCH" if *locked.lock().unwrap() > 0 => {
+            *locked.lock().unwrap() -= 1;
+            MockResponse::new(423)
+        }
+        _ => common::tus_handler(req),
+    })
+    .await;
+    let temp_file = create_temp_file(128);
+    let path = temp_file.path().into();
+    let recorder = Recorder::default();
+    let _default = tracing::subscriber::set_default(recorder.clone());
+    let options = ClientOptions {
+        locked_retry_delay: Duration::from_millis(1),
+        ..ClientOptions::default()
+    };
+    let meta = Client::new(options)
+        .upload(&path, &server.url, None, None)
+        .await
+        .unwrap();
+    assert!(meta.upload_complete());
+
+    let ops = recorder.spans("tus_op");
+    assert_eq!(ops.len(), 2);
+    let (create, upload) = (&ops[0], &ops[1]);
+    assert_eq!(create["op"], "Create");
+    assert_eq!(create["url"], server.url.to_string());
+    assert_eq!(create["status_code"], "201");
+    assert!(create.contains_key("duration_ms"));
+    assert!(!create.contains_key("retries"));
+    assert_eq!(upload["op"], "Upload");
+    assert_eq!(upload["url"], server.url.join("1").unwrap().to_string());
+    assert_eq!(upload["offset"], "0");
+    assert_eq!(upload["bytes_sent"], "128");
+    assert_eq!(upload["status_code"], "204");
+    assert_eq!(upload["retries"], "1");
+}
+
+#[tokio::test]
+async fn should_not_record_status_into_callers_span() {
+    let server = MockServer::tus().await;
+    let recorder = Recorder::default();
+    let _default = tracing::subscriber::set_default(recorder.clone());
+    let client = Client::new(ClientOptions::default());
+
+    let span = tracing::info_span!(
+        "caller",
+        status_code = tracing::field::Empty,
+        retries = tracing::field::Empty
+    );
+    let _entered = span.enter();
+    client.get_server_info(&server.url).await.unwrap();
+    client
+        .inspect(&server.url.join("1").unwrap())
+        .await
+        .unwrap();
+
+    let caller = &recorder.spans("caller")[0];
+    assert!(!caller.contains_key("status_code"));
+    assert!(recorder.spans("tus_op").is_empty());
+}
@@ -0,0 +1,364 @@
+mod common;
+
+use common::create_temp_file;
+use reqwest::header::{HeaderMap, HeaderValue};
+use std::{
+    collections::HashMap,
+    io::{Cursor, Write},
+};
+use tus_rs::{
+    client::{AutoMeta, ClientOptions},
+    error::TusError,
+    source::{ChunkSource, ReaderSource},
+    tus::{
+        checksum::ChecksumAlgorithm, headers::TusHeaders, metadata::UploadMetadata,
+        upload_meta::UploadMeta, TusVersion,
+    },
+};
+use url::Url;
+
+#[test]
+fn should_rebuild_meta_for_remote_url() {
+    let temp_file = create_temp_file(128);
+    let host = Url::parse("http://localhost/files/").unwrap();
+    let remote_url = Url::parse("http://localhost/files/abc").unwrap();
+    let meta = UploadMeta::resuming(temp_file.path().into(), host, remote_url.clone()).unwrap();
+    assert_eq!(meta.remote_url, Some(remote_url));
+    assert_eq!(meta.status.size, 128);
+    assert_eq!(meta.status.bytes_uploaded, 0);
+}
+
+#[test]
+fn should_only_accept_http_upload_hosts() {
+    let temp_file = create_temp_file(128);
+    for host in ["file:///x", "ftp://host/"] {
+        let host = Url::parse(host).unwrap();
+        let result = UploadMeta::new(temp_file.path().into(), host, None, None, None);
+        assert!(matches!(result, Err(TusError::InvalidUrl(_))));
+    }
+
+    let host = Url::parse("https://host/files/").unwrap();
+    assert!(UploadMeta::new(temp_file.path().into(), host, None, None, None).is_ok());
+}
+
+#[test]
+fn should_require_upload_url() {
+    let temp_file = create_temp_file(128);
+    let host = Url::parse("http://localhost/files/").unwrap();
+    let meta = UploadMeta::new(temp_file.path().into(), host, None, None, None).unwrap();
+    assert_eq!(meta.upload_url(), None);
+    assert!(matches!(
+        meta.require_url(),
+        Err(TusError::MissingUploadUrl)
+    ));
+
+    let meta = meta
+        .with_remote_dest("http://localhost/files/abc".to_string())
+        .unwrap();
+    assert_eq!(meta.require_url().unwrap().path(), "/files/abc");
+}
+
+#[test]
+fn should_round_trip_metadata_with_bare_keys() {
+    let temp_file = create_temp_file(128);
+    let host = Url::parse("http://localhost/files/").unwrap();
+    let extra = HashMap::from([
+        ("is_confidential".to_string(), String::new()),
+        ("owner".to_string(), "revive labs".to_string()),
+    ]);
+    let meta = UploadMeta::new(temp_file.path().into(), host, None, Some(extra), None).unwrap();
+    let encoded = meta.data64().unwrap();
+    assert!(encoded.split(',').any(|pair| pair == "is_confidential"));
+
+    let mut headers = HeaderMap::new();
+    headers.insert("upload-metadata", HeaderValue::from_str(&encoded).unwrap());
+    let decoded = TusHeaders::from(headers).upload_metadata.unwrap();
+    assert_eq!(decoded, meta.data().unwrap());
+}
+
+#[test]
+fn should_decode_multi_pair_metadata_from_response() {
+    let decode = |value: &'static str| {
+        let mut headers = HeaderMap::new();
+        headers.insert("upload-metadata", HeaderValue::from_static(value));
+        TusHeaders::from_headers(&headers).upload_metadata.unwrap()
+    };
+    // Example from the spec, "world_domination_plan.pdf" and a bare key
+    let decoded = decode("filename d29ybGRfZG9taW5hdGlvbl9wbGFuLnBkZg==,is_confidential");
+    assert_eq!(
+        decoded,
+        HashMap::from([
+            (
+                "filename".to_string(),
+                "world_domination_plan.pdf".to_string()
+            ),
+            ("is_confidential".to_string(), String::new()),
+        ])
+    );
+
+    let decoded = decode("filename YS50eHQ=, filetype dGV4dC9wbGFpbg==,owner cmV2aXZlIGxhYnM=");
+    assert_eq!(
+        decoded,
+        HashMap::from([
+            ("filename".to_string(), "a.txt".to_string()),
+            ("filetype".to_string(), "text/plain".to_string()),
+            ("owner".to_string(), "revive labs".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn should_add_auto_metadata_to_header() {
+    let temp_file = create_temp_file(128);
+    let host = Url::parse("http://localhost/files/").unwrap();
+    let extra = HashMap::from([("size".to_string(), "declared".to_string())]);
+    let meta = UploadMeta::new(temp_file.path().into(), host, None, Some(extra), None)
+        .unwrap()
+        .with_range(0..100)
+        .unwrap();
+    let options = ClientOptions {
+        auto_metadata: [
+            AutoMeta::Checksum(ChecksumAlgorithm::Sha256),
+            AutoMeta::Size,
+            AutoMeta::Modified,
+        ]
+        .into(),
+        ..ClientOptions::default()
+    };
+    let header = meta.metadata_header(&options).unwrap().unwrap();
+    let decoded = UploadMetadata::decode(&header);
+    let checksum = meta.compute_checksum(ChecksumAlgorithm::Sha256).unwrap();
+    assert_eq!(
+        decoded["checksum"].as_deref(),
+        Some(format!("sha256 {checksum}").as_str())
+    );
+    // Explicit metadata wins
+    assert_eq!(decoded["size"].as_deref(), Some("declared"));
+    assert!(decoded["modified"]
+        .as_deref()
+        .unwrap()
+        .parse::<u64>()
+        .is_ok());
+
+    let header = meta.metadata_header(&ClientOptions::default()).unwrap();
+    assert!(!UploadMetadata::decode(&header.unwrap()).contains_key("checksum"));
+}
+
+#[test]
+fn should_update_several_fields_at_once() {
+    let temp_file = create_temp_file(128);
+    let host = Url::parse("http://localhost/files/").unwrap();
+    let meta = UploadMeta::new(temp_file.path().into(), host.clone(), None, None, None).unwrap();
+    let updated = meta.update(|m| {
+        m.status.bytes_uploaded = 64;
+        m.remote_url = Some(host.join("1").unwrap());
+        m.error_count = 2;
+    });
+    assert_eq!(updated.status.bytes_uploaded, 64);
+    assert_eq!(
+        updated.upload_url().unwrap().as_str(),
+        "http://localhost/files/1"
+    );
+    assert_eq!(updated.error_count, 2);
+    assert_eq!(meta.status.bytes_uploaded, 0);
+}
+
+#[test]
+fn should_compute_file_checksum() {
+    let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+    temp_file.write_all(b"hello world").unwrap();
+    let host = Url::parse("http://localhost/files/").unwrap();
+    let meta = UploadMeta::new(temp_file.path().into(), host, None, None, None).unwrap();
+    assert_eq!(
+        meta.compute_checksum(ChecksumAlgorithm::Sha256).unwrap(),
+        "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+    );
+    assert_eq!(
+        meta.compute_checksum(ChecksumAlgorithm::Sha1).unwrap(),
+        "2aae6c35c94fcfb415dbe95f408b9ce91ee846ed"
+    );
+    assert_eq!(
+        meta.compute_checksum(ChecksumAlgorithm::Md5).unwrap(),
+        "5eb63bbbe01eeed093cb22bb8f5acdc3"
+    );
+
+    let meta = meta.with_checksum(ChecksumAlgorithm::Md5).unwrap();
+    assert_eq!(
+        meta.data().unwrap().get("checksum").unwrap(),
+        "md5 5eb63bbbe01eeed093cb22bb8f5acdc3"
+    );
+}
+
+#[test]
+fn should_encode_and_decode_upload_metadata() {
+    let metadata = UploadMetadata::new()
+        .set("filename", "world_domination_plan.pdf")
+        .set("empty", "")
+        .set_bare("is_confidential");
+    let encoded = metadata.encode();
+    assert_eq!(
+        encoded,
+        "empty ,filename d29ybGRfZG9taW5hdGlvbl9wbGFuLnBkZg==,is_confidential"
+    );
+
+    let decoded = UploadMetadata::decode(&encoded);
+    assert_eq!(
+        decoded["filename"].as_deref(),
+        Some("world_domination_plan.pdf")
+    );
+    assert_eq!(decoded["empty"].as_deref(), Some(""));
+    assert_eq!(decoded["is_confidential"], None);
+    assert_eq!(metadata.get("is_confidential"), Some(None));
+}
+
+#[test]
+fn should_send_display_filename() {
+    let temp_file = create_temp_file(128);
+    let host = Url::parse("http://localhost/files/").unwrap();
+    let meta = UploadMeta::new(temp_file.path().into(), host, None, None, None).unwrap();
+    let base_name = temp_file.path().file_name().unwrap().to_str().unwrap();
+    let decoded = UploadMetadata::decode(&meta.data64().unwrap());
+    assert_eq!(decoded["filename"].as_deref(), Some(base_name));
+
+    let meta = meta.with_filename("invoice-2024.pdf".to_string());
+    let decoded = UploadMetadata::decode(&meta.data64().unwrap());
+    assert_eq!(decoded["filename"].as_deref(), Some("invoice-2024.pdf"));
+}
+
+#[test]
+fn should_encode_binary_metadata_verbatim() {
+    let temp_file = create_temp_file(128);
+    let host = Url::parse("http://localhost/files/").unwrap();
+    let signature = vec![0xde, 0xad, 0xbe, 0xef, 0xff, 0x00];
+    let meta = UploadMeta {
+        extra_meta_binary: Some(HashMap::from([(
+            "signature".to_string(),
+            signature.clone(),
+        )])),
+        ..UploadMeta::new(temp_file.path().into(), host, None, None, None).unwrap()
+    };
+    let encoded = meta.data64().unwrap();
+    assert!(encoded.split(',').any(|pair| pair == "signature 3q2+7/8A"));
+
+    let decoded = UploadMetadata::decode_bytes(&encoded);
+    assert_eq!(decoded["signature"], Some(signature.clone()));
+    let metadata = UploadMetadata::new().set_bytes("signature", signature.clone());
+    assert_eq!(metadata.get_bytes("signature"), Some(Some(&signature[..])));
+    assert_eq!(metadata.get("signature"), None);
+}
+
+#[test]
+fn should_read_chunks_from_sources() {
+    let bytes: Vec<u8> = (0..10).collect();
+    let mut reader = ReaderSource::new(Cursor::new(bytes.clone()));
+    assert_eq!(reader.read_chunk(4, 3).unwrap(), [4, 5, 6]);
+    assert_eq!(reader.read_chunk(8, 5).unwrap(), [8, 9]);
+    // The second chunk is read while the first is still held
+    let first = reader.read_bytes(0, 4).unwrap();
+    let second = reader.read_bytes(4, 4).unwrap();
+    assert_eq!(first, [0, 1, 2, 3][..]);
+    assert_eq!(second, [4, 5, 6, 7][..]);
+    drop(first);
+    drop(second);
+    assert_eq!(reader.read_bytes(8, 4).unwrap(), [8, 9][..]);
+    let mut slice = &bytes[..];
+    assert_eq!(slice.read_chunk(0, 2).unwrap(), [0, 1]);
+    assert!(slice.read_chunk(12, 2).unwrap().is_empty());
+}
+
+#[test]
+fn should_build_exact_metadata_header() {
+    let temp_file = create_temp_file(128);
+    let host = Url::parse("http://localhost/files/").unwrap();
+    let extra = HashMap::from([
+        ("owner".to_string(), "alice".to_string()),
+        ("flag".to_string(), String::new()),
+    ]);
+    let meta = UploadMeta::new(temp_file.path().into(), host, None, Some(extra), None)
+        .unwrap()
+        .with_filename("foo.txt".to_string());
+
+    let header = meta.metadata_header(&ClientOptions::default()).unwrap();
+    assert_eq!(
+        header.as_deref(),
+        Some("filename Zm9vLnR4dA==,flag,owner YWxpY2U=")
+    );
+
+    let options = ClientOptions {
+        send_metadata: false,
+        ..ClientOptions::default()
+    };
+    assert_eq!(meta.metadata_header(&options).unwrap(), None);
+}
+
+#[test]
+fn should_reject_invalid_metadata_keys() {
+    let temp_file = create_temp_file(128);
+    let host = Url::parse("http://localhost/files/").unwrap();
+    for key in ["bad key", "a,b", "", "ключ"] {
+        let extra = HashMap::from([(key.to_string(), "value".to_string())]);
+        let meta = UploadMeta::new(
+            temp_file.path().into(),
+            host.clone(),
+            None,
+            Some(extra),
+            None,
+        )
+        .unwrap();
+        let result = meta.metadata_header(&ClientOptions::default());
+        assert!(matches!(result, Err(TusError::InvalidMetadataKey(k)) if k == key));
+    }
+}
+
+#[test]
+fn should_load_all_saved_metas_in_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    let temp_file = create_temp_file(128);
+    let host = Url::parse("http://localhost/files/").unwrap();
+    let meta = UploadMeta::new(temp_file.path().into(), host, Some(64), None, None).unwrap();
+    meta.save(&dir.path().join("a.json")).unwrap();
+    meta.with_bytes_uploaded(96)
+        .save(&dir.path().join("b.json"))
+        .unwrap();
+    std::fs::write(dir.path().join("c.json"), "not json").unwrap();
+    std::fs::write(dir.path().join("notes.txt"), "skipped").unwrap();
+    std::fs::create_dir(dir.path().join("d.json")).unwrap();
+
+    let loaded = UploadMeta::load_all(dir.path()).unwrap();
+    assert_eq!(loaded.len(), 3);
+    assert_eq!(loaded[0].as_ref().unwrap().status.bytes_uploaded, 64);
+    assert_eq!(loaded[1].as_ref().unwrap().status.bytes_uploaded, 96);
+    match &loaded[2] {
+        Err(TusError::FileReadError(message)) => assert!(message.contains("c.json")),
+        other => panic!("unexpected result: {other:?}"),
+    }
+}
+
+#[test]
+fn should_load_meta_saved_by_earlier_releases() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("meta.json");
+    let saved = r#"{
+        "upload_host": "http://localhost/files/",
+        "file_path": "/tmp/file.dat",
+        "remote_url": "http://localhost/files/abc",
+        "status": {"bytes_uploaded": 64, "size": 128},
+        "version": "1",
+        "extra_meta": null,
+        "mime_type": null,
+        "custom_headers": null,
+        "error_count": 0
+    }"#;
+    std::fs::write(&path, saved).unwrap();
+
+    let meta = UploadMeta::load(&path).unwrap();
+    assert_eq!(meta.version, TusVersion::V1_0_0);
+    assert_eq!(meta.status.bytes_uploaded, 64);
+
+    std::fs::write(&path, saved.replace(r#""1""#, r#""""#)).unwrap();
+    assert_eq!(UploadMeta::load(&path).unwrap().version, TusVersion::V1_0_0);
+    std::fs::write(&path, saved.replace(r#""1""#, r#""0.2.2""#)).unwrap();
+    assert_eq!(UploadMeta::load(&path).unwrap().version, TusVersion::V0_2_2);
+    std::fs::write(&path, saved.replace(r#""version": "1","#, "")).unwrap();
+    assert_eq!(UploadMeta::load(&path).unwrap().version, TusVersion::V1_0_0);
+}